@@ -0,0 +1,64 @@
+//! Reader for delimited text files stored in ZIP archives, streamed from the archive without extracting them first.
+use formats::{load_delimited_reader, sniff_reader, Dialect};
+use Result;
+use spreadsheet::*;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use zip::read::ZipFile;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+
+/// Get the names of the delimited text files in a ZIP archive, in the order they are stored.
+pub fn archive_members(path: &Path) -> Result<Vec<String>> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let mut members = Vec::new();
+
+    for index in 0..archive.len() {
+        let file = archive.by_index(index)?;
+
+        if file.is_file() && !is_metadata(file.name()) && member_dialect(file.name()).is_some() {
+            members.push(file.name().to_string());
+        }
+    }
+
+    Ok(members)
+}
+
+/// Load a delimited text file in a ZIP archive into a spreadsheet, guessing its dialect from its contents.
+pub fn load_archive_member(path: &Path, member: &str, spreadsheet: &Spreadsheet) -> Result<()> {
+    let fallback = member_dialect(member).ok_or_else(|| format!("{} is not a delimited text file.", member))?;
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+
+    // Compressed members can only be read from the start, so the member is opened again to load it after sniffing.
+    let dialect = sniff_reader(open_member(&mut archive, member)?, fallback)?;
+    info!("detected dialect for {} in {:?}: {}", member, path, dialect);
+
+    let report = load_delimited_reader(open_member(&mut archive, member)?, spreadsheet, &dialect)?;
+    spreadsheet.set_dialect(dialect);
+    spreadsheet.set_load_report(report);
+
+    Ok(())
+}
+
+/// Open a member of an archive for reading.
+fn open_member<'a, R: Read + Seek>(archive: &'a mut ZipArchive<R>, member: &str) -> Result<ZipFile<'a>> {
+    match archive.by_name(member) {
+        Err(ZipError::FileNotFound) => Err(format!("The archive does not contain {}.", member).into()),
+        result => Ok(result?),
+    }
+}
+
+/// Get the conventional dialect for a member of an archive, if it is a delimited text file.
+fn member_dialect(name: &str) -> Option<Dialect> {
+    Path::new(name).extension()
+        .and_then(|s| s.to_str())
+        .and_then(Dialect::for_extension)
+}
+
+/// Check if a member holds metadata added by the archiver rather than a file, such as the resource forks macOS adds
+/// next to each file.
+fn is_metadata(name: &str) -> bool {
+    name.starts_with("__MACOSX/") || name.rsplit('/').next().map_or(false, |file_name| file_name.starts_with("._"))
+}
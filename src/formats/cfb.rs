@@ -0,0 +1,246 @@
+//! Reader for Compound File Binary containers, the storage format used by legacy Office documents.
+//!
+//! A compound file is a small file system inside a file: data is split into fixed-size sectors that are chained
+//! together by a file allocation table, and a directory names the streams stored in it.
+use byteorder::{ByteOrder, LittleEndian};
+use Result;
+use std::cmp::min;
+use std::io::{Read, Seek, SeekFrom};
+
+
+/// Signature at the start of every compound file.
+const SIGNATURE: &'static [u8] = b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1";
+
+/// Marks the last sector in a chain.
+const END_OF_CHAIN: u32 = 0xFFFF_FFFE;
+
+/// Marks an unused sector.
+const FREE_SECTOR: u32 = 0xFFFF_FFFF;
+
+/// Number of sector locations of the allocation table stored in the header.
+const HEADER_DIFAT_ENTRIES: usize = 109;
+
+/// Size of a directory entry in bytes.
+const DIRECTORY_ENTRY_SIZE: usize = 128;
+
+/// Directory entry type for a stream.
+const STREAM_ENTRY: u8 = 2;
+
+/// Directory entry type for the root storage.
+const ROOT_ENTRY: u8 = 5;
+
+
+/// A stream stored in a compound file.
+struct Entry {
+    name: String,
+    kind: u8,
+    start: u32,
+    size: u64,
+}
+
+/// An open compound file.
+pub struct CompoundFile<R> {
+    reader: R,
+    sector_size: usize,
+    mini_sector_size: usize,
+
+    /// Streams smaller than this are stored in the mini stream.
+    mini_stream_cutoff: u64,
+
+    /// File allocation table: the next sector of each sector's chain.
+    fat: Vec<u32>,
+
+    /// Allocation table for sectors of the mini stream.
+    mini_fat: Vec<u32>,
+
+    /// Contents of the mini stream, which holds small streams.
+    mini_stream: Vec<u8>,
+
+    entries: Vec<Entry>,
+}
+
+impl<R: Read + Seek> CompoundFile<R> {
+    /// Open a compound file and read its allocation tables and directory.
+    pub fn open(mut reader: R) -> Result<Self> {
+        let mut header = [0; 512];
+        let length = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+
+        if &header[..8] != SIGNATURE {
+            return Err("The file is not a compound document.".into());
+        }
+
+        let sector_shift = LittleEndian::read_u16(&header[0x1E..]);
+        let mini_sector_shift = LittleEndian::read_u16(&header[0x20..]);
+        if sector_shift != 9 && sector_shift != 12 || mini_sector_shift != 6 {
+            return Err("The compound document has an unsupported sector size.".into());
+        }
+
+        let mut file = Self {
+            reader: reader,
+            sector_size: 1 << sector_shift,
+            mini_sector_size: 1 << mini_sector_shift,
+            mini_stream_cutoff: LittleEndian::read_u32(&header[0x38..]) as u64,
+            fat: Vec::new(),
+            mini_fat: Vec::new(),
+            mini_stream: Vec::new(),
+            entries: Vec::new(),
+        };
+
+        // Find the sectors that hold the allocation table. The first 109 are listed in the header, and the rest in a
+        // chain of DIFAT sectors. Neither can have more sectors than the file, which is checked before allocating
+        // anything for them.
+        let sector_count = length / file.sector_size as u64;
+        let fat_sector_count = LittleEndian::read_u32(&header[0x2C..]) as usize;
+        let difat_sector_count = LittleEndian::read_u32(&header[0x48..]);
+        if fat_sector_count as u64 > sector_count || difat_sector_count as u64 > sector_count {
+            return Err("The compound document is corrupt.".into());
+        }
+
+        let mut fat_sectors = Vec::with_capacity(fat_sector_count);
+        for i in 0..HEADER_DIFAT_ENTRIES {
+            fat_sectors.push(LittleEndian::read_u32(&header[0x4C + i * 4..]));
+        }
+
+        let mut difat_sector = LittleEndian::read_u32(&header[0x44..]);
+        for _ in 0..difat_sector_count {
+            if difat_sector == END_OF_CHAIN || difat_sector == FREE_SECTOR {
+                break;
+            }
+
+            let sector = file.read_sector(difat_sector)?;
+            let entries = file.sector_size / 4 - 1;
+            for i in 0..entries {
+                fat_sectors.push(LittleEndian::read_u32(&sector[i * 4..]));
+            }
+            difat_sector = LittleEndian::read_u32(&sector[entries * 4..]);
+        }
+
+        fat_sectors.truncate(fat_sector_count);
+        for sector in fat_sectors {
+            let sector = file.read_sector(sector)?;
+            file.fat.extend(sector.chunks(4).map(LittleEndian::read_u32));
+        }
+
+        // Read the directory.
+        let directory = file.read_chain(LittleEndian::read_u32(&header[0x30..]), None)?;
+        for entry in directory.chunks(DIRECTORY_ENTRY_SIZE) {
+            if entry.len() < DIRECTORY_ENTRY_SIZE {
+                break;
+            }
+
+            let name_length = (LittleEndian::read_u16(&entry[64..]) as usize).min(64);
+            let name: Vec<u16> = entry[..name_length].chunks(2).map(LittleEndian::read_u16).collect();
+
+            // Version 3 files may leave garbage in the upper half of the stream size.
+            let mut size = LittleEndian::read_u64(&entry[120..]);
+            if file.sector_size == 512 {
+                size &= 0xFFFF_FFFF;
+            }
+
+            file.entries.push(Entry {
+                name: String::from_utf16_lossy(&name).trim_right_matches('\0').to_string(),
+                kind: entry[66],
+                start: LittleEndian::read_u32(&entry[116..]),
+                size: size,
+            });
+        }
+
+        // Small streams are stored in the mini stream, which is itself stored as the root entry's data.
+        let mini_stream = file.entries.iter()
+            .find(|entry| entry.kind == ROOT_ENTRY)
+            .map(|entry| (entry.start, entry.size));
+
+        if let Some((start, size)) = mini_stream {
+            file.mini_stream = file.read_chain(start, Some(size))?;
+        }
+
+        let mini_fat = file.read_chain(LittleEndian::read_u32(&header[0x3C..]), None)?;
+        file.mini_fat = mini_fat.chunks(4).map(LittleEndian::read_u32).collect();
+
+        Ok(file)
+    }
+
+    /// Read the entire contents of a stream by name, or return `None` if there is no such stream.
+    ///
+    /// Stream names are compared case-insensitively, as they are in Office.
+    pub fn read_stream(&mut self, name: &str) -> Result<Option<Vec<u8>>> {
+        let stream = self.entries.iter()
+            .find(|entry| entry.kind == STREAM_ENTRY && entry.name.eq_ignore_ascii_case(name))
+            .map(|entry| (entry.start, entry.size));
+
+        match stream {
+            Some((start, size)) if size < self.mini_stream_cutoff => self.read_mini_chain(start, size).map(Some),
+            Some((start, size)) => self.read_chain(start, Some(size)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Read a single sector.
+    fn read_sector(&mut self, sector: u32) -> Result<Vec<u8>> {
+        // The header occupies the first sector-sized block of the file.
+        let offset = (sector as u64 + 1) * self.sector_size as u64;
+        let mut buffer = vec![0; self.sector_size];
+
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut buffer)?;
+
+        Ok(buffer)
+    }
+
+    /// Read a chain of sectors, optionally truncated to a given size.
+    fn read_chain(&mut self, start: u32, size: Option<u64>) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut sector = start;
+        let mut remaining = self.fat.len() + 1;
+
+        while sector != END_OF_CHAIN && sector != FREE_SECTOR {
+            // A chain can be no longer than the allocation table, so a longer one must be a loop.
+            remaining = match remaining.checked_sub(1) {
+                Some(remaining) => remaining,
+                None => return Err("The compound document is corrupt.".into()),
+            };
+
+            data.extend(self.read_sector(sector)?);
+
+            if size.map(|size| data.len() as u64 >= size).unwrap_or(false) {
+                break;
+            }
+
+            sector = match self.fat.get(sector as usize) {
+                Some(&next) => next,
+                None => return Err("The compound document is corrupt.".into()),
+            };
+        }
+
+        if let Some(size) = size {
+            data.truncate(size as usize);
+        }
+
+        Ok(data)
+    }
+
+    /// Read a chain of sectors from the mini stream.
+    fn read_mini_chain(&self, start: u32, size: u64) -> Result<Vec<u8>> {
+        // The stream cannot be larger than the mini stream holding it.
+        let mut data = Vec::with_capacity(min(size, self.mini_stream.len() as u64) as usize);
+        let mut sector = start;
+
+        while (data.len() as u64) < size && sector != END_OF_CHAIN && sector != FREE_SECTOR {
+            let offset = sector as usize * self.mini_sector_size;
+            match self.mini_stream.get(offset..offset + self.mini_sector_size) {
+                Some(bytes) => data.extend_from_slice(bytes),
+                None => return Err("The compound document is corrupt.".into()),
+            }
+
+            sector = match self.mini_fat.get(sector as usize) {
+                Some(&next) => next,
+                None => return Err("The compound document is corrupt.".into()),
+            };
+        }
+
+        data.truncate(size as usize);
+        Ok(data)
+    }
+}
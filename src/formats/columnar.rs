@@ -0,0 +1,215 @@
+//! Conversion between spreadsheet rows and Arrow record batches, shared by the columnar formats.
+use arrow::array::{Array, ArrayRef, AsArray, BooleanBuilder, Float64Builder, Int64Builder, StringArray, StringBuilder};
+use arrow::array::{BinaryBuilder, FixedSizeBinaryBuilder, LargeBinaryBuilder};
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{DataType, Field, Float32Type, Float64Type, Schema, SchemaRef};
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use formats::{for_each_page, infer_column_types, parse_boolean, ColumnType};
+use Result;
+use spreadsheet::*;
+use std::str::FromStr;
+use std::sync::Arc;
+
+
+/// Load the columns and rows of a record batch reader into a spreadsheet, recording the type of each column.
+pub fn load_reader<R: RecordBatchReader>(reader: R, spreadsheet: &Spreadsheet) -> Result<()> {
+    let schema = reader.schema();
+
+    let columns = schema.fields().iter().map(|field| field.name().clone()).collect();
+    spreadsheet.insert_columns(InsertPosition::End, columns)?;
+
+    for (index, field) in schema.fields().iter().enumerate() {
+        spreadsheet.set_column_type(index as i64, Some(&field.data_type().to_string()))?;
+    }
+
+    let mut rows = spreadsheet.bulk_insert()?;
+    for batch in reader {
+        load_batch(&batch?, &mut rows)?;
+    }
+
+    rows.finish()
+}
+
+/// Infer a schema for a spreadsheet from its values.
+///
+/// Columns keep the type recorded when they were loaded, as long as all of their values can still be converted to it.
+/// Otherwise a column containing only booleans is stored as booleans, and a column containing only plain numbers is
+/// stored as 64-bit integers if none have a fraction, or as doubles otherwise. Every other column is stored as text.
+/// Empty cells are nulls and do not affect the type.
+pub fn infer_schema(spreadsheet: &Spreadsheet) -> Result<SchemaRef> {
+    let columns = spreadsheet.get_columns();
+    let mut recorded_types = spreadsheet.get_column_types().into_iter()
+        .map(|data_type| data_type.and_then(|data_type| DataType::from_str(&data_type).ok()))
+        .collect::<Vec<_>>();
+
+    if recorded_types.iter().any(Option::is_some) {
+        for_each_page(spreadsheet, |_, rows| {
+            for (index, recorded_type) in recorded_types.iter_mut().enumerate() {
+                let convertible = match *recorded_type {
+                    Some(ref data_type) => build_array(data_type, &rows, index).is_ok(),
+                    None => continue,
+                };
+
+                if !convertible {
+                    info!("column {} no longer fits its recorded type {:?}", index, recorded_type);
+                    *recorded_type = None;
+                }
+            }
+
+            Ok(())
+        })?;
+    }
+
+    let types = infer_column_types(spreadsheet)?.into_iter().map(|column_type| match column_type {
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::Integer => DataType::Int64,
+        ColumnType::Float => DataType::Float64,
+        ColumnType::Text => DataType::Utf8,
+    });
+
+    let fields = columns.into_iter()
+        .zip(types.zip(recorded_types))
+        .map(|(name, (data_type, recorded_type))| {
+            Field::new(name, recorded_type.unwrap_or(data_type), true)
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// Pass the rows of a spreadsheet to a function as record batches of the given schema, a page at a time so that large
+/// spreadsheets are not loaded into memory all at once.
+pub fn for_each_batch<F>(spreadsheet: &Spreadsheet, schema: &SchemaRef, mut f: F) -> Result<()>
+    where F: FnMut(RecordBatch) -> Result<()>
+{
+    for_each_page(spreadsheet, |_, rows| {
+        let columns = schema.fields().iter()
+            .enumerate()
+            .map(|(index, field)| build_array(field.data_type(), &rows, index))
+            .collect::<Result<Vec<_>>>()?;
+
+        f(RecordBatch::try_new(schema.clone(), columns)?)
+    })
+}
+
+/// Insert the rows of a record batch into a spreadsheet. Nulls become empty cells.
+fn load_batch(batch: &RecordBatch, rows: &mut BulkInsert) -> Result<()> {
+    let options = FormatOptions::default().with_null("");
+    let formatters = batch.columns().iter()
+        .map(|column| ArrayFormatter::try_new(column.as_ref(), &options))
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+    for row in 0..batch.num_rows() {
+        let values = batch.columns().iter()
+            .zip(&formatters)
+            .map(|(column, formatter)| cell_text(column, formatter, row))
+            .collect();
+
+        rows.push(values)?;
+    }
+
+    Ok(())
+}
+
+/// Get the text of a cell in an Arrow array.
+fn cell_text(column: &ArrayRef, formatter: &ArrayFormatter, row: usize) -> String {
+    if column.is_null(row) {
+        return String::new();
+    }
+
+    // Arrow always displays a fraction for floating point numbers, so whole numbers are formatted here instead to read
+    // the same as they would in any other file.
+    match *column.data_type() {
+        DataType::Float32 => column.as_primitive::<Float32Type>().value(row).to_string(),
+        DataType::Float64 => column.as_primitive::<Float64Type>().value(row).to_string(),
+        _ => formatter.value(row).to_string(),
+    }
+}
+
+/// Build an Arrow array of the given type from one column of a page of rows.
+///
+/// Binary values are parsed from the hexadecimal text they are displayed as when loaded.
+fn build_array(data_type: &DataType, rows: &[Vec<Option<String>>], index: usize) -> Result<ArrayRef> {
+    let values = rows.iter().map(|row| match row.get(index) {
+        Some(&Some(ref value)) if !value.is_empty() => Some(value.as_str()),
+        _ => None,
+    });
+
+    Ok(match *data_type {
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(rows.len());
+            for value in values {
+                let value = value.map(|value| parse_boolean(value).ok_or(format!("{} is not a boolean.", value)));
+                builder.append_option(value.transpose()?);
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(rows.len());
+            for value in values {
+                builder.append_option(value.map(str::parse).transpose()?);
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(rows.len());
+            for value in values {
+                builder.append_option(value.map(str::parse).transpose()?);
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Utf8 => {
+            let mut builder = StringBuilder::with_capacity(rows.len(), 0);
+            for value in values {
+                builder.append_option(value);
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Binary => {
+            let mut builder = BinaryBuilder::with_capacity(rows.len(), 0);
+            for value in values {
+                builder.append_option(value.map(parse_hex).transpose()?);
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::LargeBinary => {
+            let mut builder = LargeBinaryBuilder::with_capacity(rows.len(), 0);
+            for value in values {
+                builder.append_option(value.map(parse_hex).transpose()?);
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::FixedSizeBinary(size) => {
+            let mut builder = FixedSizeBinaryBuilder::with_capacity(rows.len(), size);
+            for value in values {
+                match value {
+                    Some(value) => builder.append_value(parse_hex(value)?)?,
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        ref data_type => {
+            // Let Arrow parse any other type from the text it was displayed as when loaded.
+            let text = values.collect::<StringArray>();
+            let options = CastOptions {
+                safe: false,
+                ..CastOptions::default()
+            };
+
+            cast_with_options(&text, data_type, &options)?
+        }
+    })
+}
+
+/// Decode binary data from the hexadecimal text Arrow displays it as.
+fn parse_hex(text: &str) -> Result<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.bytes().all(|b| (b as char).is_digit(16)) {
+        return Err(format!("{} is not hexadecimal binary data.", text).into());
+    }
+
+    (0..text.len() / 2)
+        .map(|i| Ok(u8::from_str_radix(&text[i * 2..i * 2 + 2], 16)?))
+        .collect()
+}
@@ -0,0 +1,193 @@
+//! Transparent decompression and compression of files compressed as a whole, such as `data.csv.gz`.
+use bzip2;
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use flate2;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use progress::{LoadProgress, ProgressReader};
+use Result;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+
+/// Default compression level for xz, the same as the `xz` command.
+const XZ_LEVEL: u32 = 6;
+
+
+/// Codecs a whole file can be compressed with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Get the codec for a file extension, if it is a compressed file extension.
+    pub fn for_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" | "bzip2" => Some(Compression::Bzip2),
+            "xz" => Some(Compression::Xz),
+            _ => None,
+        }
+    }
+
+    /// Get the codec for a file path based on its extension.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|s| s.to_str())
+            .and_then(Self::for_extension)
+    }
+
+    /// Get the codec a stream is compressed with from the bytes at its start, leaving it positioned where it was.
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Self>> {
+        let start = reader.seek(SeekFrom::Current(0))?;
+
+        let mut magic = Vec::with_capacity(6);
+        reader.by_ref().take(6).read_to_end(&mut magic)?;
+        reader.seek(SeekFrom::Start(start))?;
+
+        Ok(if magic.starts_with(b"\x1F\x8B") {
+            Some(Compression::Gzip)
+        } else if magic.starts_with(b"\x28\xB5\x2F\xFD") {
+            Some(Compression::Zstd)
+        } else if magic.starts_with(b"BZh") && magic.get(3).map_or(false, |&level| level >= b'1' && level <= b'9') {
+            // The block size level follows the signature, so that text starting with "BZh" is not mistaken for bzip2.
+            Some(Compression::Bzip2)
+        } else if magic.starts_with(b"\xFD7zXZ\x00") {
+            Some(Compression::Xz)
+        } else {
+            None
+        })
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+            Compression::Xz => "xz",
+        })
+    }
+}
+
+
+/// Writer that compresses everything written to it, or passes it through unchanged if there is no codec.
+///
+/// `finish` must be called once everything has been written, to write the end of the compressed stream.
+pub struct CompressWriter<W: Write> {
+    encoder: Encoder<W>,
+}
+
+enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(ZstdEncoder<'static, W>),
+    Bzip2(BzEncoder<W>),
+    Xz(XzEncoder<W>),
+}
+
+impl<W: Write> CompressWriter<W> {
+    /// Create a writer compressing with the given codec.
+    pub fn new(inner: W, compression: Option<Compression>) -> io::Result<Self> {
+        let encoder = match compression {
+            None => Encoder::Plain(inner),
+            Some(Compression::Gzip) => Encoder::Gzip(GzEncoder::new(inner, flate2::Compression::default())),
+            Some(Compression::Zstd) => Encoder::Zstd(ZstdEncoder::new(inner, 0)?),
+            Some(Compression::Bzip2) => Encoder::Bzip2(BzEncoder::new(inner, bzip2::Compression::default())),
+            Some(Compression::Xz) => Encoder::Xz(XzEncoder::new(inner, XZ_LEVEL)),
+        };
+
+        Ok(Self {
+            encoder: encoder,
+        })
+    }
+
+    /// Finish the compressed stream and return the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        let mut inner = match self.encoder {
+            Encoder::Plain(inner) => inner,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+            Encoder::Bzip2(encoder) => encoder.finish()?,
+            Encoder::Xz(encoder) => encoder.finish()?,
+        };
+        inner.flush()?;
+
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for CompressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.encoder {
+            Encoder::Plain(ref mut inner) => inner.write(buf),
+            Encoder::Gzip(ref mut encoder) => encoder.write(buf),
+            Encoder::Zstd(ref mut encoder) => encoder.write(buf),
+            Encoder::Bzip2(ref mut encoder) => encoder.write(buf),
+            Encoder::Xz(ref mut encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.encoder {
+            Encoder::Plain(ref mut inner) => inner.flush(),
+            Encoder::Gzip(ref mut encoder) => encoder.flush(),
+            Encoder::Zstd(ref mut encoder) => encoder.flush(),
+            Encoder::Bzip2(ref mut encoder) => encoder.flush(),
+            Encoder::Xz(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+
+/// Open a file for reading, decompressing it as it is read if it starts with the signature of a known codec.
+///
+/// Reading the file counts towards the progress of a load running in the background, if one is given.
+pub fn open_decompressed(path: &Path, progress: Option<&Arc<LoadProgress>>) -> Result<Box<Read>> {
+    let mut file = File::open(path)?;
+    let compression = Compression::detect(&mut file)?;
+    let file = ProgressReader::new(file, progress);
+
+    Ok(match compression {
+        None => Box::new(file),
+        Some(Compression::Gzip) => Box::new(MultiGzDecoder::new(file)),
+        Some(Compression::Zstd) => Box::new(ZstdDecoder::new(file)?),
+        Some(Compression::Bzip2) => Box::new(MultiBzDecoder::new(file)),
+        Some(Compression::Xz) => Box::new(XzDecoder::new_multi_decoder(file)),
+    })
+}
+
+/// Create a file for writing, compressed with the codec given by its extension if it has one.
+pub fn create_compressed(path: &Path) -> Result<CompressWriter<File>> {
+    Ok(CompressWriter::new(File::create(path)?, Compression::for_path(path))?)
+}
+
+/// Get a file path without its compressed file extension, so that the extension before it gives the format of the
+/// file's contents.
+pub fn uncompressed_path(path: &Path) -> PathBuf {
+    match Compression::for_path(path) {
+        Some(_) => path.with_extension(""),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Get the extension of a file path in lowercase, ignoring any compressed file extension after it.
+pub fn uncompressed_extension(path: &Path) -> Option<String> {
+    uncompressed_path(path).extension()
+        .and_then(|s| s.to_str())
+        .map(str::to_lowercase)
+}
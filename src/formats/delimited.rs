@@ -0,0 +1,577 @@
+use csv;
+use encoding_rs::{Encoding, UTF_8};
+use encoding_rs_io::DecodeReaderBytes;
+use formats::{create_compressed, decode_reader, open_decompressed, uncompressed_path};
+use formats::{ColumnNaming, DiagnosticKind, EncodeWriter, LoadReport, SAVE_PAGE_SIZE};
+use Result;
+use spreadsheet::*;
+use std::cell::RefCell;
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+
+
+/// Describes how fields and records are laid out in a delimited text file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Dialect {
+    /// Byte that separates fields within a record.
+    pub delimiter: u8,
+
+    /// Byte used to quote fields containing special characters.
+    pub quote: u8,
+
+    /// Byte used to escape quotes inside a quoted field, or `None` if quotes are escaped by doubling them.
+    pub escape: Option<u8>,
+
+    /// Sequence that ends each record.
+    pub terminator: Terminator,
+
+    /// Whether the first record contains column names.
+    pub has_headers: bool,
+
+    /// How columns are named if the file does not have a header row.
+    pub column_naming: ColumnNaming,
+
+    /// What to do with records that have a different number of fields than there are columns.
+    pub ragged_rows: RaggedRows,
+
+    /// Number of lines at the start of the file to ignore, such as titles or comments preceding the data.
+    pub skip_lines: usize,
+
+    /// Character encoding of the file.
+    pub encoding: &'static Encoding,
+
+    /// Whether the file starts with a byte order mark.
+    pub bom: bool,
+}
+
+/// Line endings used to terminate records.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Terminator {
+    CRLF,
+    LF,
+    CR,
+}
+
+/// Policies for loading ragged records, which have fewer or more fields than there are columns.
+///
+/// Every ragged record is listed in the load report along with what was done with it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RaggedRows {
+    /// Pad short records with empty cells, and skip records with extra fields.
+    Pad,
+
+    /// Pad short records with empty cells, and drop the extra fields of long records.
+    Truncate,
+
+    /// Pad short records with empty cells, and add columns to hold the extra fields of long records.
+    AddColumns,
+
+    /// Skip all ragged records.
+    Reject,
+}
+
+impl Default for RaggedRows {
+    fn default() -> Self {
+        RaggedRows::AddColumns
+    }
+}
+
+impl Dialect {
+    /// Create a dialect using the given field delimiter.
+    pub fn with_delimiter(delimiter: u8) -> Self {
+        Self {
+            delimiter: delimiter,
+            quote: b'"',
+            escape: None,
+            terminator: Terminator::LF,
+            has_headers: true,
+            column_naming: ColumnNaming::default(),
+            ragged_rows: RaggedRows::default(),
+            skip_lines: 0,
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+
+    /// Comma-separated values.
+    pub fn csv() -> Self {
+        Self::with_delimiter(b',')
+    }
+
+    /// Tab-separated values.
+    pub fn tsv() -> Self {
+        Self::with_delimiter(b'\t')
+    }
+
+    /// Get the conventional dialect for a file extension, if it is a delimited text extension.
+    pub fn for_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "csv" => Some(Self::csv()),
+            "tsv" | "tab" | "txt" => Some(Self::tsv()),
+            "psv" => Some(Self::with_delimiter(b'|')),
+            "ssv" => Some(Self::with_delimiter(b';')),
+            _ => None,
+        }
+    }
+
+    /// Get the conventional dialect for a file path based on its extension, ignoring any compressed file extension.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        uncompressed_path(path).extension()
+            .and_then(|s| s.to_str())
+            .and_then(Self::for_extension)
+    }
+
+    /// Create a CSV reader using this dialect, skipping past any leading lines.
+    fn reader<R: Read>(&self, inner: R) -> Result<csv::Reader<BufReader<DecodeReaderBytes<R, Vec<u8>>>>> {
+        Ok(self.parser(self.decode(inner)?))
+    }
+
+    /// Decode a stream of text in the encoding of this dialect, skipping past any leading lines.
+    fn decode<R: Read>(&self, inner: R) -> Result<BufReader<DecodeReaderBytes<R, Vec<u8>>>> {
+        let mut file = BufReader::new(decode_reader(inner, self.encoding));
+
+        let mut line = Vec::new();
+        for _ in 0..self.skip_lines {
+            line.clear();
+            if file.read_until(self.line_break(), &mut line)? == 0 {
+                break;
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Create a CSV reader for decoded text using this dialect.
+    ///
+    /// Records may have any number of fields, so that ragged records can be handled by the caller.
+    pub fn parser<R: Read>(&self, text: R) -> csv::Reader<R> {
+        // Any line ending is accepted when reading, since files frequently mix them.
+        csv::Reader::from_reader(text)
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            .has_headers(self.has_headers)
+            .flexible(true)
+    }
+
+    /// Get the byte that ends each line.
+    pub fn line_break(&self) -> u8 {
+        match self.terminator {
+            Terminator::CR => b'\r',
+            _ => b'\n',
+        }
+    }
+
+    /// Create a CSV writer using this dialect.
+    fn writer<W: Write>(&self, inner: W) -> Result<csv::Writer<EncodeWriter<W>>> {
+        let file = EncodeWriter::new(inner, self.encoding, self.bom)?;
+
+        let writer = csv::Writer::from_writer(file)
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .record_terminator(match self.terminator {
+                Terminator::CRLF => csv::RecordTerminator::CRLF,
+                Terminator::LF => csv::RecordTerminator::Any(b'\n'),
+                Terminator::CR => csv::RecordTerminator::Any(b'\r'),
+            });
+
+        Ok(match self.escape {
+            Some(escape) => writer.double_quote(false).escape(escape),
+            None => writer.double_quote(true),
+        })
+    }
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self::csv()
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Delimiter: {}, quote: {}, escape: ", byte_name(self.delimiter), byte_name(self.quote))?;
+
+        match self.escape {
+            Some(escape) => write!(f, "{}", byte_name(escape))?,
+            None => write!(f, "doubled quotes")?,
+        }
+
+        write!(f, ", line endings: {:?}, header row: {}", self.terminator, if self.has_headers {
+            "yes"
+        } else {
+            "no"
+        })?;
+
+        if self.skip_lines > 0 {
+            write!(f, ", skipped lines: {}", self.skip_lines)?;
+        }
+
+        write!(f, ", encoding: {}", self.encoding.name())?;
+        if self.bom {
+            write!(f, " with BOM")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Get a human-readable name for a delimiter or quote byte.
+fn byte_name(byte: u8) -> String {
+    match byte {
+        b',' => String::from("comma"),
+        b'\t' => String::from("tab"),
+        b';' => String::from("semicolon"),
+        b'|' => String::from("pipe"),
+        b' ' => String::from("space"),
+        b'"' => String::from("double quote"),
+        b'\'' => String::from("single quote"),
+        b'\\' => String::from("backslash"),
+        byte => format!("{:?}", byte as char),
+    }
+}
+
+
+/// Load a delimited text file into a database, returning the problems found in it.
+pub fn load_delimited(path: &Path, spreadsheet: &Spreadsheet, dialect: &Dialect) -> Result<LoadReport> {
+    load_delimited_reader(open_decompressed(path, spreadsheet.load_progress())?, spreadsheet, dialect)
+}
+
+/// Load delimited text from a stream into a database, returning the problems found in it.
+///
+/// Ragged records are handled using the dialect's policy instead of failing the load.
+pub fn load_delimited_reader<R: Read>(reader: R, spreadsheet: &Spreadsheet, dialect: &Dialect) -> Result<LoadReport> {
+    let tracker = Rc::new(RefCell::new(LineTracker::new(dialect)));
+    let mut reader = dialect.parser(TrackingReader {
+        inner: dialect.decode(reader)?,
+        tracker: tracker.clone(),
+    });
+
+    // The number of columns is set by the first record, whether or not it holds the column names.
+    let mut column_count = None;
+    let mut rows = spreadsheet.bulk_insert()?;
+
+    while let Some(mut record) = read_record(&mut reader)? {
+        let line = tracker.borrow_mut().record_line();
+
+        if record.iter().any(|field| field.contains('\u{FFFD}')) {
+            tracker.borrow_mut().report.add(line, DiagnosticKind::Unparseable, format!(
+                "contains bytes that are not valid {}, which were replaced.",
+                dialect.encoding.name()
+            ));
+        }
+
+        let columns = match column_count {
+            Some(columns) => columns,
+            None => {
+                column_count = Some(record.len());
+
+                if dialect.has_headers {
+                    spreadsheet.insert_columns(InsertPosition::End, record)?;
+                    continue;
+                }
+
+                let names = (0..record.len()).map(|i| dialect.column_naming.name(i)).collect();
+                spreadsheet.insert_columns(InsertPosition::End, names)?;
+                record.len()
+            }
+        };
+
+        let fields = record.len();
+        if fields != columns {
+            let action = match (dialect.ragged_rows, fields < columns) {
+                (RaggedRows::Reject, _) | (RaggedRows::Pad, false) => None,
+                (_, true) => Some("padded it with empty cells"),
+                (RaggedRows::Truncate, false) => {
+                    record.truncate(columns);
+                    Some("dropped the extra fields")
+                }
+                (RaggedRows::AddColumns, false) => {
+                    let names = (columns..fields).map(|i| dialect.column_naming.name(i)).collect();
+                    spreadsheet.insert_columns(InsertPosition::End, names)?;
+                    column_count = Some(fields);
+                    Some("added columns for the extra fields")
+                }
+            };
+
+            tracker.borrow_mut().report.add(line, DiagnosticKind::Ragged, format!(
+                "expected {} fields but found {}, so {}.",
+                columns,
+                fields,
+                action.unwrap_or("skipped the record")
+            ));
+
+            if action.is_none() {
+                continue;
+            }
+        }
+
+        let mut values = record.into_iter().map(Some).collect::<Vec<_>>();
+        if values.len() < columns {
+            values.resize(columns, None);
+        }
+        rows.push(values)?;
+    }
+    rows.finish()?;
+
+    let mut report = mem::replace(&mut tracker.borrow_mut().report, LoadReport::default());
+    report.sort();
+
+    Ok(report)
+}
+
+/// Read just the beginning of a delimited text file, returning the column names and up to `max_rows` rows.
+///
+/// Rows are returned as they are in the file, so they may not all have the same number of fields.
+pub fn preview_delimited(path: &Path, dialect: &Dialect, max_rows: usize) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+    let mut reader = dialect.reader(open_decompressed(path, None)?)?;
+
+    let columns = column_names(&mut reader, dialect)?;
+    let mut rows = Vec::new();
+
+    for record in reader.records().take(max_rows) {
+        rows.push(record?);
+    }
+
+    Ok((columns, rows))
+}
+
+/// Get the column names for a file from its first record. Columns are named by position if the file does not have a
+/// header row.
+fn column_names<R: io::Read>(reader: &mut csv::Reader<R>, dialect: &Dialect) -> Result<Vec<String>> {
+    let headers = reader.headers()?;
+
+    if dialect.has_headers {
+        Ok(headers)
+    } else {
+        Ok((0..headers.len()).map(|i| dialect.column_naming.name(i)).collect())
+    }
+}
+
+/// Read the next record from a CSV reader, or `None` at the end of the text.
+fn read_record<R: Read>(reader: &mut csv::Reader<R>) -> Result<Option<Vec<String>>> {
+    let mut record = Vec::new();
+
+    loop {
+        match reader.next_str() {
+            csv::NextField::Data(field) => record.push(field.to_string()),
+            csv::NextField::EndOfRecord => return Ok(Some(record)),
+            csv::NextField::EndOfCsv => return Ok(None),
+            csv::NextField::Error(error) => return Err(error.into()),
+        }
+    }
+}
+
+/// Save a spreadsheet to a delimited text file, compressed if the file has a compressed file extension.
+pub fn save_delimited(path: &Path, spreadsheet: &Spreadsheet, dialect: &Dialect) -> Result<()> {
+    let mut file = create_compressed(path)?;
+    let mut writer = dialect.writer(&mut file)?;
+
+    if dialect.has_headers {
+        writer.encode(spreadsheet.get_columns())?;
+    }
+
+    // Write rows a page at a time so that large spreadsheets are not loaded into memory all at once.
+    let row_count = spreadsheet.get_row_count();
+    let mut start = 0;
+    while start < row_count {
+        let end = min(start + SAVE_PAGE_SIZE, row_count) - 1;
+
+        for row in spreadsheet.get_rows(start, end)? {
+            writer.encode(row)?;
+        }
+
+        start = end + 1;
+    }
+
+    writer.flush()?;
+    drop(writer);
+    file.finish()?;
+
+    Ok(())
+}
+
+
+/// Reader that passes the text it reads through a line tracker before it is parsed.
+struct TrackingReader<R> {
+    inner: R,
+    tracker: Rc<RefCell<LineTracker>>,
+}
+
+impl<R: Read> Read for TrackingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+
+        if count == 0 {
+            self.tracker.borrow_mut().finish();
+        } else {
+            self.tracker.borrow_mut().scan(&buf[..count]);
+        }
+
+        Ok(count)
+    }
+}
+
+/// Quoting state of the field being read, following the states of the CSV parser.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Quoting {
+    FieldStart,
+    Unquoted,
+    Quoted,
+    Escaped,
+    Closed,
+}
+
+/// Follows the quoting of delimited text a byte at a time, in step with the CSV parser, to find where each record
+/// starts without parsing its fields.
+#[derive(Clone)]
+pub struct RecordScanner {
+    delimiter: u8,
+    quote: u8,
+    escape: Option<u8>,
+
+    /// Quoting state of the field being read.
+    quoting: Quoting,
+
+    /// Whether the next byte that is not a line ending starts a record.
+    record_start: bool,
+}
+
+impl RecordScanner {
+    pub fn new(dialect: &Dialect) -> Self {
+        Self {
+            delimiter: dialect.delimiter,
+            quote: dialect.quote,
+            escape: dialect.escape,
+            quoting: Quoting::FieldStart,
+            record_start: true,
+        }
+    }
+
+    /// Follow the next byte of text, returning whether it is the first byte of a record.
+    pub fn scan(&mut self, byte: u8) -> bool {
+        let line_ending = byte == b'\n' || byte == b'\r';
+        let separator = byte == self.delimiter || line_ending;
+
+        // Blank lines between records are skipped by the parser.
+        let starts_record = self.record_start && !line_ending;
+        if starts_record {
+            self.record_start = false;
+        }
+
+        self.quoting = match self.quoting {
+            Quoting::FieldStart | Quoting::Unquoted if separator => Quoting::FieldStart,
+            Quoting::FieldStart if byte == self.quote => Quoting::Quoted,
+            Quoting::FieldStart | Quoting::Unquoted => Quoting::Unquoted,
+            Quoting::Quoted if Some(byte) == self.escape => Quoting::Escaped,
+            Quoting::Quoted if byte == self.quote => Quoting::Closed,
+            Quoting::Quoted | Quoting::Escaped => Quoting::Quoted,
+            Quoting::Closed if byte == self.quote => Quoting::Quoted,
+            Quoting::Closed if separator => Quoting::FieldStart,
+            Quoting::Closed => Quoting::Unquoted,
+        };
+
+        if line_ending && self.quoting == Quoting::FieldStart {
+            self.record_start = true;
+        }
+
+        starts_record
+    }
+
+    /// Check if the text scanned so far ends inside a quoted field.
+    pub fn in_quotes(&self) -> bool {
+        self.quoting == Quoting::Quoted || self.quoting == Quoting::Escaped
+    }
+}
+
+/// Follows the lines and quoting of delimited text as it is read, in step with the CSV parser, to find the line each
+/// record starts on and to report broken quoting that the parser silently works around.
+struct LineTracker {
+    scanner: RecordScanner,
+    line_break: u8,
+
+    /// Line being read.
+    line: u64,
+
+    /// Line the quote opening the field being read is on.
+    quote_line: u64,
+
+    /// Lines the records read start on, that the parser has not returned yet.
+    record_lines: VecDeque<u64>,
+
+    /// Problems found so far.
+    report: LoadReport,
+}
+
+impl LineTracker {
+    fn new(dialect: &Dialect) -> Self {
+        let first_line = dialect.skip_lines as u64 + 1;
+
+        Self {
+            scanner: RecordScanner::new(dialect),
+            line_break: dialect.line_break(),
+            line: first_line,
+            quote_line: first_line,
+            record_lines: VecDeque::new(),
+            report: LoadReport::default(),
+        }
+    }
+
+    /// Follow a chunk of text read.
+    fn scan(&mut self, text: &[u8]) {
+        for &byte in text {
+            let quoting = self.scanner.quoting;
+            if self.scanner.scan(byte) {
+                self.record_lines.push_back(self.line);
+            }
+
+            let line = self.line;
+            match (quoting, self.scanner.quoting) {
+                (Quoting::FieldStart, Quoting::Quoted) => self.quote_line = line,
+                (Quoting::Unquoted, Quoting::Unquoted) if byte == self.scanner.quote => {
+                    self.malformed(line, "a quote appears inside an unquoted field.");
+                }
+                (Quoting::Closed, Quoting::Unquoted) => {
+                    self.malformed(line, "text follows the closing quote of a field.");
+                }
+                _ => {}
+            }
+
+            if byte == self.line_break {
+                self.line += 1;
+            }
+        }
+    }
+
+    /// Check the end of the text once it has all been read.
+    fn finish(&mut self) {
+        if self.scanner.in_quotes() {
+            let line = self.quote_line;
+            self.malformed(line, "a quoted field is not closed before the end of the file.");
+            self.scanner.quoting = Quoting::FieldStart;
+        }
+    }
+
+    /// Get the line the next record returned by the parser starts on.
+    fn record_line(&mut self) -> u64 {
+        let line = self.line;
+        self.record_lines.pop_front().unwrap_or(line)
+    }
+
+    /// Report broken quoting on a line, once per line.
+    fn malformed(&mut self, line: u64, message: &str) {
+        let reported = self.report.diagnostics.iter()
+            .rev()
+            .take_while(|diagnostic| diagnostic.line == line)
+            .any(|diagnostic| diagnostic.kind == DiagnosticKind::Malformed);
+
+        if !reported {
+            self.report.add(line, DiagnosticKind::Malformed, message);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Character encoding detection and conversion for text formats.
+use chardetng::EncodingDetector;
+use encoding_rs::*;
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::str;
+
+
+/// Get the encodings that can be chosen for reading and writing text files.
+pub fn encodings() -> Vec<&'static Encoding> {
+    vec![
+        UTF_8,
+        UTF_16LE,
+        UTF_16BE,
+        WINDOWS_1252,
+        ISO_8859_15,
+        WINDOWS_1250,
+        ISO_8859_2,
+        WINDOWS_1251,
+        KOI8_R,
+        WINDOWS_1253,
+        WINDOWS_1254,
+        WINDOWS_1255,
+        WINDOWS_1256,
+        WINDOWS_1257,
+        WINDOWS_1258,
+        WINDOWS_874,
+        MACINTOSH,
+        SHIFT_JIS,
+        EUC_JP,
+        EUC_KR,
+        GBK,
+        GB18030,
+        BIG5,
+    ]
+}
+
+/// Detect the encoding of a sample taken from the start of a file.
+///
+/// Returns the encoding and whether the sample starts with a byte order mark. If there is no byte order mark the
+/// encoding is guessed from the content, preferring UTF-8 if the sample is valid UTF-8.
+pub fn detect_encoding(sample: &[u8], complete: bool) -> (&'static Encoding, bool) {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return (encoding, true);
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, complete);
+
+    (detector.guess(None, true), false)
+}
+
+/// Wrap a reader so that it produces UTF-8 decoded from the given encoding.
+///
+/// A byte order mark at the start of the stream is removed, and takes precedence over the given encoding.
+pub fn decode_reader<R: Read>(reader: R, encoding: &'static Encoding) -> DecodeReaderBytes<R, Vec<u8>> {
+    DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_override(true)
+        .strip_bom(true)
+        .build(reader)
+}
+
+
+/// A writer that converts UTF-8 text written to it into another character encoding.
+///
+/// Characters that cannot be represented in the target encoding are written as `?`.
+pub struct EncodeWriter<W: Write> {
+    inner: W,
+    encoding: &'static Encoding,
+    encoder: Encoder,
+
+    /// Bytes of an incomplete UTF-8 sequence left over from the previous write.
+    pending: Vec<u8>,
+
+    /// Scratch buffer for encoded output.
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncodeWriter<W> {
+    /// Create a new encoding writer, optionally writing a byte order mark first if the encoding has one.
+    pub fn new(mut inner: W, encoding: &'static Encoding, bom: bool) -> io::Result<Self> {
+        if bom {
+            inner.write_all(byte_order_mark(encoding))?;
+        }
+
+        Ok(Self {
+            inner: inner,
+            encoding: encoding,
+            encoder: encoding.new_encoder(),
+            pending: Vec::new(),
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Encode a complete string to the underlying writer.
+    fn encode(&mut self, text: &str) -> io::Result<()> {
+        self.buffer.clear();
+
+        if self.encoding == UTF_8 {
+            return self.inner.write_all(text.as_bytes());
+        } else if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            // encoding_rs only decodes UTF-16, so it must be encoded by hand.
+            for unit in text.encode_utf16() {
+                let (high, low) = ((unit >> 8) as u8, unit as u8);
+                if self.encoding == UTF_16BE {
+                    self.buffer.push(high);
+                    self.buffer.push(low);
+                } else {
+                    self.buffer.push(low);
+                    self.buffer.push(high);
+                }
+            }
+
+            return self.inner.write_all(&self.buffer);
+        }
+
+        let mut remaining = text;
+        loop {
+            let needed = self.encoder
+                .max_buffer_length_from_utf8_without_replacement(remaining.len())
+                .unwrap_or(remaining.len() * 4);
+            self.buffer.reserve(needed);
+
+            let (result, read) = self.encoder
+                .encode_from_utf8_to_vec_without_replacement(remaining, &mut self.buffer, false);
+            remaining = &remaining[read..];
+
+            match result {
+                EncoderResult::InputEmpty => break,
+                EncoderResult::OutputFull => {}
+                EncoderResult::Unmappable(_) => self.buffer.push(b'?'),
+            }
+        }
+
+        self.inner.write_all(&self.buffer)
+    }
+}
+
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pending = mem::replace(&mut self.pending, Vec::new());
+        pending.extend_from_slice(buf);
+
+        // Only encode complete characters; a multi-byte sequence may be split across writes.
+        let valid = match str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            Err(e) => match e.error_len() {
+                Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                None => e.valid_up_to(),
+            },
+        };
+
+        self.encode(str::from_utf8(&pending[..valid]).unwrap())?;
+        pending.drain(..valid);
+        self.pending = pending;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Get the byte order mark for an encoding, or an empty slice if it does not have one.
+fn byte_order_mark(encoding: &'static Encoding) -> &'static [u8] {
+    if encoding == UTF_8 {
+        b"\xEF\xBB\xBF"
+    } else if encoding == UTF_16LE {
+        b"\xFF\xFE"
+    } else if encoding == UTF_16BE {
+        b"\xFE\xFF"
+    } else {
+        b""
+    }
+}
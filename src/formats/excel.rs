@@ -0,0 +1,221 @@
+//! Helpers shared by the Excel workbook formats.
+use formats::ColumnNaming;
+use Result;
+use spreadsheet::*;
+
+
+/// How a numeric cell value should be displayed, based on its number format.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NumberKind {
+    Number,
+    Date,
+    DateTime,
+    Time,
+}
+
+/// Get the kind of value displayed by one of the built-in number formats.
+pub fn builtin_format_kind(id: u32) -> NumberKind {
+    match id {
+        14..=17 | 27..=36 | 50..=58 => NumberKind::Date,
+        18..=21 | 45..=47 => NumberKind::Time,
+        22 => NumberKind::DateTime,
+        _ => NumberKind::Number,
+    }
+}
+
+/// Get the kind of value displayed by a custom number format code, such as `yyyy-mm-dd hh:mm`.
+pub fn format_code_kind(code: &str) -> NumberKind {
+    let mut date = false;
+    let mut time = false;
+    let mut months_or_minutes = false;
+    let mut chars = code.chars();
+
+    // Only the first section of the format (for positive numbers) is relevant.
+    while let Some(c) = chars.next() {
+        match c.to_ascii_lowercase() {
+            ';' => break,
+
+            // Skip over literal text, colors, conditions, and padding characters.
+            '"' => while let Some(c) = chars.next() {
+                if c == '"' {
+                    break;
+                }
+            },
+            '[' => {
+                let mut section = String::new();
+                while let Some(c) = chars.next() {
+                    if c == ']' {
+                        break;
+                    }
+                    section.push(c.to_ascii_lowercase());
+                }
+
+                // Elapsed time, such as `[h]:mm`.
+                if section.chars().all(|c| c == 'h' || c == 'm' || c == 's') && !section.is_empty() {
+                    time = true;
+                }
+            }
+            '\\' | '_' | '*' => {
+                chars.next();
+            }
+
+            'y' | 'd' => date = true,
+            'h' | 's' => time = true,
+            'm' => months_or_minutes = true,
+            _ => {}
+        }
+    }
+
+    // `m` means minutes when it appears alongside hours or seconds, and months otherwise.
+    if months_or_minutes && !time {
+        date = true;
+    }
+
+    match (date, time) {
+        (true, true) => NumberKind::DateTime,
+        (true, false) => NumberKind::Date,
+        (false, true) => NumberKind::Time,
+        (false, false) => NumberKind::Number,
+    }
+}
+
+/// Format a numeric cell value as text.
+///
+/// Dates and times are stored as a number of days since the workbook epoch, which is either 1900 or 1904. They are
+/// formatted as ISO 8601 dates and times.
+pub fn format_number(value: f64, kind: NumberKind, date1904: bool) -> String {
+    if kind == NumberKind::Number || !value.is_finite() || value < 0.0 {
+        return format_plain_number(value);
+    }
+
+    // Convert the serial date to days since the Unix epoch. The 1900 date system incorrectly treats 1900 as a leap
+    // year, so dates before the fictional 29 February 1900 are shifted by a day.
+    let mut days = value.floor() as i64;
+    let mut seconds = ((value - value.floor()) * 86400.0).round() as i64;
+    if seconds >= 86400 {
+        days += 1;
+        seconds -= 86400;
+    }
+
+    days -= if date1904 {
+        24107
+    } else if days < 60 {
+        25568
+    } else {
+        25569
+    };
+
+    let (year, month, day) = civil_from_days(days);
+    let time = format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    match kind {
+        NumberKind::Date if seconds == 0 => format!("{:04}-{:02}-{:02}", year, month, day),
+        NumberKind::Time => time,
+        _ => format!("{:04}-{:02}-{:02} {}", year, month, day, time),
+    }
+}
+
+/// Format a number without any special display format.
+pub fn format_plain_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+/// Convert a number of days since 1970-01-01 into a year, month, and day.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+
+/// Inserts worksheet rows into a spreadsheet, using the first non-blank row as column names.
+///
+/// The writer must be finished once every row is written.
+pub struct RowWriter<'a> {
+    spreadsheet: &'a Spreadsheet,
+    rows: BulkInsert<'a>,
+    columns: usize,
+    last_row: Option<u32>,
+
+    /// Blank rows seen since the last row with values. These are only inserted once another row with values is
+    /// found, so that formatted but empty rows at the end of a sheet are ignored.
+    pending_blank_rows: u32,
+}
+
+impl<'a> RowWriter<'a> {
+    pub fn new(spreadsheet: &'a Spreadsheet) -> Result<Self> {
+        Ok(Self {
+            spreadsheet: spreadsheet,
+            rows: spreadsheet.bulk_insert()?,
+            columns: 0,
+            last_row: None,
+            pending_blank_rows: 0,
+        })
+    }
+
+    /// Write a row with the given 1-based row number.
+    pub fn write(&mut self, number: u32, mut values: Vec<String>) -> Result<()> {
+        // Trailing empty cells are usually just formatting.
+        while values.last().map(String::is_empty).unwrap_or(false) {
+            values.pop();
+        }
+
+        if values.is_empty() {
+            if self.last_row.is_some() {
+                self.pending_blank_rows += 1;
+            }
+            return Ok(());
+        }
+
+        match self.last_row {
+            None => {
+                self.columns = values.len();
+                self.spreadsheet.insert_columns(InsertPosition::End, values)?;
+            }
+            Some(last) => {
+                // Rows that are missing from the sheet entirely are blank, too.
+                let skipped = number.saturating_sub(last + 1);
+                let blank_rows = if skipped > self.pending_blank_rows { skipped } else { self.pending_blank_rows };
+
+                for _ in 0..blank_rows {
+                    self.insert(Vec::new())?;
+                }
+                self.insert(values)?;
+            }
+        }
+
+        self.last_row = Some(number);
+        self.pending_blank_rows = 0;
+
+        Ok(())
+    }
+
+    fn insert(&mut self, mut values: Vec<String>) -> Result<()> {
+        // Add columns for values past the end of the header row.
+        if values.len() > self.columns {
+            let names = (self.columns..values.len()).map(|i| ColumnNaming::default().name(i)).collect();
+            self.spreadsheet.insert_columns(InsertPosition::End, names)?;
+            self.columns = values.len();
+        }
+
+        values.resize(self.columns, String::new());
+        self.rows.push(values)
+    }
+
+    /// Finish inserting the rows written.
+    pub fn finish(self) -> Result<()> {
+        self.rows.finish()
+    }
+}
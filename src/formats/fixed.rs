@@ -0,0 +1,158 @@
+//! Reader for fixed-width text files, where each column occupies the same range of characters on every line.
+use encoding_rs::{Encoding, UTF_8};
+use formats::{decode_reader, open_decompressed, ColumnNaming};
+use Result;
+use spreadsheet::*;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::usize;
+
+
+/// Describes where each column lies on the lines of a fixed-width text file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FixedWidthLayout {
+    /// Character offsets at which each column starts and ends, in order. The end is exclusive, and may lie past the end
+    /// of every line to take the rest of the line.
+    pub columns: Vec<(usize, usize)>,
+
+    /// Whether the first line contains column names.
+    pub has_headers: bool,
+
+    /// How columns are named if the file does not have a header line.
+    pub column_naming: ColumnNaming,
+
+    /// Number of lines at the start of the file to ignore, such as titles or comments preceding the data.
+    pub skip_lines: usize,
+
+    /// Character encoding of the file.
+    pub encoding: &'static Encoding,
+}
+
+impl FixedWidthLayout {
+    /// Create a layout with a column starting at the beginning of the line and at each of the given offsets. Each
+    /// column ends where the next one starts, and the last column takes the rest of the line.
+    pub fn from_boundaries(boundaries: &[usize]) -> Self {
+        let mut starts = boundaries.iter().cloned().filter(|&offset| offset > 0).collect::<Vec<_>>();
+        starts.sort();
+        starts.dedup();
+        starts.insert(0, 0);
+
+        let ends = starts.iter().skip(1).cloned().chain(Some(usize::MAX));
+
+        Self {
+            columns: starts.iter().cloned().zip(ends).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Get the offsets at which each column after the first starts.
+    pub fn boundaries(&self) -> Vec<usize> {
+        self.columns.iter().map(|&(start, _)| start).filter(|&start| start > 0).collect()
+    }
+
+    /// Split a line into the values of each column, with surrounding whitespace removed. A layout without any columns
+    /// treats the whole line as a single column.
+    pub fn split(&self, line: &str) -> Vec<String> {
+        if self.columns.is_empty() {
+            return vec![line.trim().to_string()];
+        }
+
+        let chars = line.chars().collect::<Vec<_>>();
+
+        self.columns.iter()
+            .map(|&(start, end)| {
+                let end = end.min(chars.len());
+                if start < end {
+                    chars[start..end].iter().collect::<String>().trim().to_string()
+                } else {
+                    String::new()
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for FixedWidthLayout {
+    fn default() -> Self {
+        Self {
+            columns: Vec::new(),
+            has_headers: true,
+            column_naming: ColumnNaming::default(),
+            skip_lines: 0,
+            encoding: UTF_8,
+        }
+    }
+}
+
+
+/// Load a fixed-width text file into a spreadsheet. Blank lines are ignored.
+pub fn load_fixed_width(path: &Path, spreadsheet: &Spreadsheet, layout: &FixedWidthLayout) -> Result<()> {
+    let mut lines = read_lines(open_decompressed(path, spreadsheet.load_progress())?, layout)?;
+
+    let columns = column_names(&mut lines, layout)?;
+    spreadsheet.insert_columns(InsertPosition::End, columns)?;
+
+    let mut rows = spreadsheet.bulk_insert()?;
+    for line in lines {
+        rows.push(layout.split(&line?))?;
+    }
+
+    rows.finish()
+}
+
+/// Read up to `max_lines` lines from the beginning of a fixed-width text file as they are, including the header line.
+/// Blank lines are left out, as they are when loading.
+pub fn preview_fixed_width(path: &Path, layout: &FixedWidthLayout, max_lines: usize) -> Result<Vec<String>> {
+    read_lines(open_decompressed(path, None)?, layout)?.take(max_lines).collect()
+}
+
+/// Guess where columns start from a sample of lines, by finding the characters that follow a run of spaces on every
+/// line.
+pub fn guess_boundaries(lines: &[String]) -> Vec<usize> {
+    let lines = lines.iter().map(|line| line.chars().collect::<Vec<_>>()).collect::<Vec<_>>();
+    let width = lines.iter().map(Vec::len).max().unwrap_or(0);
+
+    // A position is blank if no line has anything but a space there.
+    let blank = (0..width)
+        .map(|offset| lines.iter().all(|line| line.get(offset).map_or(true, |c| c.is_whitespace())))
+        .collect::<Vec<_>>();
+
+    (1..width).filter(|&offset| blank[offset - 1] && !blank[offset]).collect()
+}
+
+/// Read a file with the given layout, skipping past any leading lines, and iterate over its non-blank lines.
+fn read_lines(file: Box<Read>, layout: &FixedWidthLayout) -> Result<Box<Iterator<Item = Result<String>>>> {
+    let file = BufReader::new(decode_reader(file, layout.encoding));
+
+    let lines = file.lines()
+        .skip(layout.skip_lines)
+        .map(|line| line.map(|line| line.trim_right_matches('\r').to_string()).map_err(From::from))
+        .filter(|line| match *line {
+            Ok(ref line) => !line.trim().is_empty(),
+            Err(_) => true,
+        });
+
+    Ok(Box::new(lines))
+}
+
+/// Get the column names for a file from its first line. Columns are named by position if the file does not have a
+/// header line, or if a column has no name.
+fn column_names<I: Iterator<Item = Result<String>>>(lines: &mut I, layout: &FixedWidthLayout) -> Result<Vec<String>> {
+    let count = if layout.columns.is_empty() { 1 } else { layout.columns.len() };
+
+    if !layout.has_headers {
+        return Ok((0..count).map(|i| layout.column_naming.name(i)).collect());
+    }
+
+    let headers = match lines.next() {
+        Some(line) => layout.split(&line?),
+        None => Vec::new(),
+    };
+
+    Ok((0..count)
+        .map(|index| match headers.get(index) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => layout.column_naming.name(index),
+        })
+        .collect())
+}
@@ -0,0 +1,249 @@
+//! Browsing delimited text files in place, without loading them into a database.
+use csv;
+use encoding_rs::UTF_8;
+use formats::{Compression, Dialect, RecordScanner};
+use memmap2::Mmap;
+use progress::LoadProgress;
+use Result;
+use std::fs::File;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+
+/// Number of records between the offsets kept in the index. Reading a row means parsing at most this many records
+/// before it.
+const INDEX_INTERVAL: usize = 1024;
+
+/// Number of bytes indexed between checks for whether indexing has been cancelled.
+const INDEX_CHUNK_SIZE: usize = 1 << 20;
+
+
+/// A delimited text file mapped into memory and read in place.
+///
+/// The file is indexed on a background thread, by recording where every `INDEX_INTERVAL`th record starts. Rows can be
+/// read as soon as they are indexed, by parsing forward from the nearest recorded offset. Every record is a row of its
+/// own, so the dialect's policy for ragged rows does not apply.
+pub struct IndexedFile {
+    path: PathBuf,
+
+    /// Contents of the file.
+    data: Arc<Mmap>,
+
+    dialect: Dialect,
+
+    /// Names of the columns, from the header row or the scheme for naming them.
+    columns: Vec<String>,
+
+    /// Offsets of every `INDEX_INTERVAL`th record after the header row, found so far.
+    offsets: Arc<RwLock<Vec<usize>>>,
+
+    /// Whether the whole file has been indexed.
+    complete: Arc<AtomicBool>,
+
+    /// Progress of indexing the file, counting the rows indexed so far.
+    progress: Arc<LoadProgress>,
+}
+
+impl IndexedFile {
+    /// Map a delimited text file into memory, read its column names, and start indexing it in the background.
+    ///
+    /// Only files in UTF-8 or a single-byte encoding can be read in place, since the bytes of other encodings may be
+    /// mistaken for delimiters and quotes.
+    pub fn open(path: &Path, dialect: &Dialect) -> Result<Self> {
+        if dialect.encoding != UTF_8 && !dialect.encoding.is_single_byte() {
+            return Err(format!("Files in {} cannot be viewed without importing them.", dialect.encoding.name()).into());
+        }
+
+        let mut file = File::open(path)?;
+        if Compression::detect(&mut file)?.is_some() {
+            return Err("Compressed files cannot be viewed without importing them.".into());
+        }
+        let data = Arc::new(unsafe { Mmap::map(&file)? });
+
+        let start = data_start(&data, dialect);
+        let first_end = first_record_end(&data[start..], dialect);
+        let first_record = match first_end {
+            Some(end) => read_records(&data[start..start + end], dialect, 1)?.pop().unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        let columns = if dialect.has_headers {
+            first_record.into_iter().map(|name| name.unwrap_or_default()).collect()
+        } else {
+            (0..first_record.len()).map(|i| dialect.column_naming.name(i)).collect()
+        };
+
+        // Without a header row, the first record is the first row.
+        let rows_start = match first_end {
+            Some(end) if dialect.has_headers => start + end,
+            _ => start,
+        };
+
+        let indexed = Self {
+            progress: Arc::new(LoadProgress::new(Some(data.len() as u64))),
+            path: path.to_path_buf(),
+            data: data,
+            dialect: *dialect,
+            columns: columns,
+            offsets: Arc::new(RwLock::new(Vec::new())),
+            complete: Arc::new(AtomicBool::new(false)),
+        };
+
+        {
+            let data = indexed.data.clone();
+            let dialect = *dialect;
+            let offsets = indexed.offsets.clone();
+            let complete = indexed.complete.clone();
+            let progress = indexed.progress.clone();
+
+            thread::spawn(move || {
+                build_index(&data, rows_start, &dialect, &offsets, &progress);
+                complete.store(!progress.is_cancelled(), Ordering::Relaxed);
+            });
+        }
+
+        Ok(indexed)
+    }
+
+    /// Get the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the names of the columns.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Get the number of rows indexed so far.
+    pub fn row_count(&self) -> i64 {
+        self.progress.rows()
+    }
+
+    /// Check if the whole file has been indexed.
+    pub fn is_complete(&self) -> bool {
+        self.complete.load(Ordering::Relaxed)
+    }
+
+    /// Get the progress of indexing the file.
+    pub fn progress(&self) -> &Arc<LoadProgress> {
+        &self.progress
+    }
+
+    /// Read a number of rows starting at a row, from the file. Fewer rows are returned if the rest are not indexed yet.
+    pub fn get_rows(&self, start: i64, count: i64) -> Result<Vec<Vec<Option<String>>>> {
+        let count = count.min(self.row_count() - start);
+        if start < 0 || count <= 0 {
+            return Ok(Vec::new());
+        }
+
+        let start = start as usize;
+        let offset = match self.offsets.read().unwrap().get(start / INDEX_INTERVAL) {
+            Some(&offset) => offset,
+            None => return Ok(Vec::new()),
+        };
+
+        let skip = start % INDEX_INTERVAL;
+        let mut rows = read_records(&self.data[offset..], &self.dialect, skip + count as usize)?;
+
+        Ok(rows.split_off(skip.min(rows.len())))
+    }
+}
+
+impl Drop for IndexedFile {
+    fn drop(&mut self) {
+        // Stop indexing a file that is no longer being viewed. The indexing thread keeps the file mapped until then.
+        self.progress.cancel();
+    }
+}
+
+
+/// Get the offset the first record starts at, after any byte order mark and skipped lines.
+fn data_start(data: &[u8], dialect: &Dialect) -> usize {
+    let mut start = if data.starts_with(b"\xEF\xBB\xBF") && dialect.encoding == UTF_8 {
+        3
+    } else {
+        0
+    };
+
+    for _ in 0..dialect.skip_lines {
+        match data[start..].iter().position(|&byte| byte == dialect.line_break()) {
+            Some(position) => start += position + 1,
+            None => return data.len(),
+        }
+    }
+
+    start
+}
+
+/// Find where the first record of some text ends, which is where the second one starts, if the text has any records.
+fn first_record_end(data: &[u8], dialect: &Dialect) -> Option<usize> {
+    let mut scanner = RecordScanner::new(dialect);
+    let mut found = false;
+
+    for (offset, &byte) in data.iter().enumerate() {
+        if scanner.scan(byte) {
+            if found {
+                return Some(offset);
+            }
+            found = true;
+        }
+    }
+
+    if found {
+        Some(data.len())
+    } else {
+        None
+    }
+}
+
+/// Record the offset of every `INDEX_INTERVAL`th record of the text from a record start onwards, and count the records
+/// as rows, until indexing is cancelled.
+fn build_index(data: &[u8], start: usize, dialect: &Dialect, offsets: &RwLock<Vec<usize>>, progress: &LoadProgress) {
+    let mut scanner = RecordScanner::new(dialect);
+    let mut rows = 0;
+
+    for (chunk_start, chunk) in (start..).step_by(INDEX_CHUNK_SIZE).zip(data[start..].chunks(INDEX_CHUNK_SIZE)) {
+        if progress.is_cancelled() {
+            return;
+        }
+
+        let counted = rows;
+        for (offset, &byte) in chunk.iter().enumerate() {
+            if scanner.scan(byte) {
+                if rows % INDEX_INTERVAL == 0 {
+                    offsets.write().unwrap().push(chunk_start + offset);
+                }
+                rows += 1;
+            }
+        }
+
+        // Rows are only counted once the offsets needed to read them are recorded.
+        progress.add_rows(rows - counted);
+        progress.set_bytes(chunk_start + chunk.len());
+    }
+}
+
+/// Parse up to a number of records from the start of some text.
+fn read_records(data: &[u8], dialect: &Dialect, count: usize) -> Result<Vec<Vec<Option<String>>>> {
+    let mut reader = dialect.parser(data);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+
+    while records.len() < count {
+        match reader.next_bytes() {
+            csv::NextField::Data(field) => {
+                let (text, _) = dialect.encoding.decode_without_bom_handling(field);
+                record.push(Some(text.into_owned()));
+            }
+            csv::NextField::EndOfRecord => records.push(mem::replace(&mut record, Vec::new())),
+            csv::NextField::EndOfCsv => break,
+            csv::NextField::Error(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,43 @@
+//! Reader and writer for Arrow IPC files, also known as Feather version 2.
+use arrow::ipc::reader::{FileReader, StreamReader};
+use arrow::ipc::writer::FileWriter;
+use formats::columnar;
+use Result;
+use spreadsheet::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::path::Path;
+
+
+/// Bytes at the start of every Arrow IPC file.
+const MAGIC: &'static [u8] = b"ARROW1";
+
+
+/// Load an Arrow IPC file into a spreadsheet. Files containing an Arrow IPC stream are also accepted.
+pub fn load_ipc(path: &Path, spreadsheet: &Spreadsheet) -> Result<()> {
+    let mut file = File::open(path)?;
+
+    let mut magic = [0; 6];
+    let is_file = file.read_exact(&mut magic).is_ok() && magic == MAGIC;
+    file.seek(SeekFrom::Start(0))?;
+
+    if is_file {
+        columnar::load_reader(FileReader::try_new(BufReader::new(file), None)?, spreadsheet)
+    } else {
+        columnar::load_reader(StreamReader::try_new(BufReader::new(file), None)?, spreadsheet)
+    }
+}
+
+/// Save a spreadsheet as an Arrow IPC file, keeping the column types recorded when it was loaded where possible.
+pub fn save_ipc(path: &Path, spreadsheet: &Spreadsheet) -> Result<()> {
+    let schema = columnar::infer_schema(spreadsheet)?;
+
+    let mut writer = FileWriter::try_new(BufWriter::new(File::create(path)?), &schema)?;
+    columnar::for_each_batch(spreadsheet, &schema, |batch| {
+        writer.write(&batch)?;
+        Ok(())
+    })?;
+    writer.finish()?;
+
+    Ok(())
+}
@@ -0,0 +1,308 @@
+//! Reader and writer for JSON documents containing records, either as an array of objects or as newline-delimited
+//! JSON.
+use formats::{create_compressed, for_each_page, infer_column_types, open_decompressed, parse_boolean, ColumnType};
+use progress::LoadProgress;
+use Result;
+use serde::de::{self, Deserializer as DeserializerTrait, SeqAccess, Visitor};
+use serde_json::{self, Deserializer, Map, Number, Value};
+use spreadsheet::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+
+/// Name of the column that holds records that are not objects.
+const VALUE_COLUMN: &'static str = "value";
+
+
+/// Options for reading and writing JSON records.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct JsonOptions {
+    /// Whether nested objects are flattened into columns named by their dotted path, such as `address.city`. If not,
+    /// nested objects are stored as JSON text.
+    pub flatten: bool,
+
+    /// Whether empty cells are written as `null` instead of empty strings.
+    pub nulls: bool,
+
+    /// Whether columns containing only numbers or only booleans are written as JSON numbers or booleans instead of
+    /// strings. Empty cells in these columns are always written as `null`.
+    pub typed: bool,
+
+    /// Whether records in a JSON array are indented across multiple lines. Newline-delimited JSON is always written
+    /// one record per line.
+    pub pretty: bool,
+
+    /// How records are laid out when writing. Records are read in either layout.
+    pub layout: JsonLayout,
+}
+
+/// Ways of laying out the records of a JSON file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JsonLayout {
+    /// A single array containing every record.
+    Array,
+    /// Newline-delimited JSON, with one record on each line.
+    Lines,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self {
+            flatten: true,
+            nulls: false,
+            typed: true,
+            pretty: false,
+            layout: JsonLayout::Array,
+        }
+    }
+}
+
+
+/// Load JSON records into a spreadsheet.
+///
+/// The file may contain an array of objects, or a sequence of objects such as newline-delimited JSON. Every key that
+/// appears in any object becomes a column, in the order they are first seen. Arrays are stored as JSON text.
+pub fn load_json(path: &Path, spreadsheet: &Spreadsheet, options: &JsonOptions) -> Result<()> {
+    // Find all of the columns first, so that every row can be inserted with all of its cells.
+    let mut columns = Vec::new();
+    let mut indexes = HashMap::new();
+
+    for_each_record(path, spreadsheet.load_progress(), |record| {
+        for (name, _) in fields(record, options) {
+            if !indexes.contains_key(&name) {
+                indexes.insert(name.clone(), columns.len());
+                columns.push(name);
+            }
+        }
+
+        Ok(())
+    })?;
+
+    spreadsheet.insert_columns(InsertPosition::End, columns.clone())?;
+
+    let mut rows = spreadsheet.bulk_insert()?;
+    for_each_record(path, spreadsheet.load_progress(), |record| {
+        let mut values = vec![String::new(); columns.len()];
+        for (name, value) in fields(record, options) {
+            values[indexes[&name]] = value;
+        }
+
+        rows.push(values)
+    })?;
+
+    rows.finish()
+}
+
+/// Save a spreadsheet as a JSON array of records, one object per row keyed by column name.
+pub fn save_json(path: &Path, spreadsheet: &Spreadsheet, options: &JsonOptions) -> Result<()> {
+    let columns = spreadsheet.get_columns();
+    let types = column_types(spreadsheet, options)?;
+    let mut file = create_compressed(path)?;
+    let mut writer = BufWriter::new(&mut file);
+    let mut first = true;
+
+    writer.write_all(b"[")?;
+
+    for_each_page(spreadsheet, |_, rows| {
+        for row in rows {
+            let record = record(&columns, row, &types, options);
+
+            writer.write_all(if first { b"\n" } else { b",\n" })?;
+            first = false;
+
+            if options.pretty {
+                // Strings never contain raw newlines in JSON text, so every line can be indented to nest the record.
+                let text = serde_json::to_string_pretty(&record)?;
+                writer.write_all(b"  ")?;
+                writer.write_all(text.replace('\n', "\n  ").as_bytes())?;
+            } else {
+                serde_json::to_writer(&mut writer, &record)?;
+            }
+        }
+
+        Ok(())
+    })?;
+
+    writer.write_all(if first { b"]\n" } else { b"\n]\n" })?;
+    writer.flush()?;
+    drop(writer);
+    file.finish()?;
+
+    Ok(())
+}
+
+/// Save a spreadsheet as newline-delimited JSON, one object per line keyed by column name.
+pub fn save_ndjson(path: &Path, spreadsheet: &Spreadsheet, options: &JsonOptions) -> Result<()> {
+    let columns = spreadsheet.get_columns();
+    let types = column_types(spreadsheet, options)?;
+    let mut file = create_compressed(path)?;
+    let mut writer = BufWriter::new(&mut file);
+
+    for_each_page(spreadsheet, |_, rows| {
+        for row in rows {
+            serde_json::to_writer(&mut writer, &record(&columns, row, &types, options))?;
+            writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    })?;
+
+    writer.flush()?;
+    drop(writer);
+    file.finish()?;
+
+    Ok(())
+}
+
+/// Infer the type of JSON value to write for each column.
+fn column_types(spreadsheet: &Spreadsheet, options: &JsonOptions) -> Result<Vec<ColumnType>> {
+    if options.typed {
+        infer_column_types(spreadsheet)
+    } else {
+        Ok(vec![ColumnType::Text; spreadsheet.get_column_count() as usize])
+    }
+}
+
+/// Build the JSON object for a row.
+fn record(columns: &[String], row: Vec<Option<String>>, types: &[ColumnType], options: &JsonOptions) -> Value {
+    let mut object = Map::new();
+    let mut values = row.into_iter();
+
+    for (name, &column_type) in columns.iter().zip(types) {
+        let value = values.next().and_then(|value| value).unwrap_or_default();
+
+        let value = if value.is_empty() {
+            if options.nulls || column_type != ColumnType::Text {
+                Value::Null
+            } else {
+                Value::String(value)
+            }
+        } else {
+            match column_type {
+                ColumnType::Boolean => parse_boolean(&value).map(Value::Bool),
+                ColumnType::Integer | ColumnType::Float => number(&value).map(Value::Number),
+                ColumnType::Text => None,
+            }.unwrap_or(Value::String(value))
+        };
+
+        object.insert(name.clone(), value);
+    }
+
+    Value::Object(object)
+}
+
+/// Parse a numeric cell value into a JSON number.
+fn number(value: &str) -> Option<Number> {
+    if value.contains('.') {
+        value.parse().ok().and_then(Number::from_f64)
+    } else {
+        value.parse::<i64>().ok().map(Number::from)
+    }
+}
+
+/// Stream each record in a JSON file to a function. Reading the file counts towards the progress of a load, if one is
+/// given.
+fn for_each_record<F>(path: &Path, progress: Option<&Arc<LoadProgress>>, mut f: F) -> Result<()>
+    where F: FnMut(Value) -> Result<()>
+{
+    let mut reader = BufReader::new(open_decompressed(path, progress)?);
+
+    // JSON text may start with a byte order mark, which the parser does not accept.
+    if reader.fill_buf()?.starts_with(b"\xEF\xBB\xBF") {
+        reader.consume(3);
+    }
+
+    if first_byte(&mut reader)? == Some(b'[') {
+        let mut deserializer = Deserializer::from_reader(reader);
+        (&mut deserializer).deserialize_seq(RecordVisitor(&mut f))?;
+        deserializer.end()?;
+    } else {
+        for record in Deserializer::from_reader(reader).into_iter() {
+            f(record?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the first byte of a reader that is not whitespace, without consuming it.
+fn first_byte<R: BufRead>(reader: &mut R) -> Result<Option<u8>> {
+    loop {
+        let (whitespace, found) = {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(None);
+            }
+
+            let whitespace = buffer.iter().take_while(|b| b.is_ascii_whitespace()).count();
+            (whitespace, buffer.get(whitespace).cloned())
+        };
+
+        reader.consume(whitespace);
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+}
+
+/// Get the column names and cell values of a record.
+fn fields(record: Value, options: &JsonOptions) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+
+    match record {
+        Value::Object(object) => for (key, value) in object {
+            flatten(key, value, options, &mut fields);
+        },
+        value => fields.push((String::from(VALUE_COLUMN), json_text(&value))),
+    }
+
+    fields
+}
+
+/// Add the fields for a value, flattening it if it is a nested object.
+fn flatten(name: String, value: Value, options: &JsonOptions, fields: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(ref object) if options.flatten && !object.is_empty() => {
+            for (key, value) in object {
+                flatten(format!("{}.{}", name, key), value.clone(), options, fields);
+            }
+        }
+        value => fields.push((name, json_text(&value))),
+    }
+}
+
+/// Get the text of a cell for a JSON value. Strings are unquoted, nulls are empty, and arrays and objects are
+/// written as JSON text.
+pub fn json_text(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref s) => s.clone(),
+        Value::Array(_) | Value::Object(_) => serde_json::to_string(value).unwrap_or_default(),
+        _ => value.to_string(),
+    }
+}
+
+
+/// Passes each element of a JSON array to a function as it is parsed, so that the whole array is never in memory.
+struct RecordVisitor<'a, F: 'a>(&'a mut F);
+
+impl<'de, 'a, F> Visitor<'de> for RecordVisitor<'a, F>
+    where F: FnMut(Value) -> Result<()>
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of JSON records")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> ::std::result::Result<(), A::Error> {
+        while let Some(record) = seq.next_element()? {
+            (self.0)(record).map_err(de::Error::custom)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Readers and writers for the file formats spreadsheets can be loaded from and saved to.
+mod archive;
+mod cfb;
+mod columnar;
+mod compression;
+mod delimited;
+mod encoding;
+mod excel;
+mod fixed;
+mod indexed;
+mod ipc;
+mod json;
+mod ods;
+mod parquet;
+mod report;
+mod sniff;
+mod sql;
+mod sqlite;
+mod xls;
+mod xlsx;
+mod xml;
+
+pub use self::archive::*;
+pub use self::compression::*;
+pub use self::delimited::*;
+pub use self::encoding::*;
+pub use self::fixed::*;
+pub use self::indexed::*;
+pub use self::ipc::*;
+pub use self::json::*;
+pub use self::ods::*;
+pub use self::parquet::*;
+pub use self::report::*;
+pub use self::sniff::*;
+pub use self::sql::*;
+pub use self::sqlite::*;
+pub use self::xls::*;
+pub use self::xlsx::*;
+
+use Result;
+use spreadsheet::Spreadsheet;
+use std::cmp::min;
+
+
+/// Number of rows to fetch from the database at a time while saving.
+const SAVE_PAGE_SIZE: i64 = 10000;
+
+
+/// Scheme for naming columns of a file without a header row.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColumnNaming {
+    /// `column_1`, `column_2`, and so on.
+    Numbered,
+
+    /// `A`, `B`, and so on up to `Z`, followed by `AA`, `AB`, and so on, like spreadsheet column letters.
+    Lettered,
+}
+
+impl ColumnNaming {
+    /// Get the name of the column at an index.
+    pub fn name(self, index: usize) -> String {
+        match self {
+            ColumnNaming::Numbered => format!("column_{}", index + 1),
+            ColumnNaming::Lettered => {
+                let mut letters = Vec::new();
+                let mut remaining = index + 1;
+
+                while remaining > 0 {
+                    remaining -= 1;
+                    letters.push((b'A' + (remaining % 26) as u8) as char);
+                    remaining /= 26;
+                }
+
+                letters.into_iter().rev().collect()
+            }
+        }
+    }
+}
+
+impl Default for ColumnNaming {
+    fn default() -> Self {
+        ColumnNaming::Numbered
+    }
+}
+
+
+/// Type of the values in a column, inferred from its cells.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColumnType {
+    Boolean,
+    Integer,
+    Float,
+    Text,
+}
+
+impl ColumnType {
+    /// Get the type of a non-empty cell value.
+    fn of(value: &str) -> Self {
+        if is_plain_number(value) {
+            if value.contains('.') { ColumnType::Float } else { ColumnType::Integer }
+        } else if parse_boolean(value).is_some() {
+            ColumnType::Boolean
+        } else {
+            ColumnType::Text
+        }
+    }
+
+    /// Get the type of a column containing values of both types.
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Integer, ColumnType::Float) | (ColumnType::Float, ColumnType::Integer) => ColumnType::Float,
+            _ => ColumnType::Text,
+        }
+    }
+}
+
+
+/// Infer the type of each column of a spreadsheet from its values.
+///
+/// Empty cells do not affect the type of their column, and columns without any values are text.
+fn infer_column_types(spreadsheet: &Spreadsheet) -> Result<Vec<ColumnType>> {
+    let mut types: Vec<Option<ColumnType>> = vec![None; spreadsheet.get_column_count() as usize];
+
+    for_each_page(spreadsheet, |_, rows| {
+        for row in rows {
+            for (value, column_type) in row.iter().zip(types.iter_mut()) {
+                if let Some(ref value) = *value {
+                    if !value.is_empty() {
+                        let value_type = ColumnType::of(value);
+                        *column_type = Some(column_type.map_or(value_type, |t| t.merge(value_type)));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(types.into_iter().map(|column_type| column_type.unwrap_or(ColumnType::Text)).collect())
+}
+
+/// Pass every page of rows of a spreadsheet to a function, along with the index of its first row, so that large
+/// spreadsheets are not loaded into memory all at once.
+fn for_each_page<F>(spreadsheet: &Spreadsheet, mut f: F) -> Result<()>
+    where F: FnMut(i64, Vec<Vec<Option<String>>>) -> Result<()>
+{
+    let row_count = spreadsheet.get_row_count();
+    let mut start = 0;
+    while start < row_count {
+        let end = min(start + SAVE_PAGE_SIZE, row_count) - 1;
+        f(start, spreadsheet.get_rows(start, end)?)?;
+        start = end + 1;
+    }
+
+    Ok(())
+}
+
+/// Check if a value can be stored as a number without changing how it is displayed.
+///
+/// Values with leading zeros, such as postal codes, trailing zeros, exponents, or more than the 15 significant digits
+/// spreadsheet applications keep are left as text.
+fn is_plain_number(value: &str) -> bool {
+    let unsigned = if value.starts_with('-') { &value[1..] } else { value };
+    let (integer, fraction) = match unsigned.find('.') {
+        Some(index) => (&unsigned[..index], &unsigned[index + 1..]),
+        None => (unsigned, ""),
+    };
+
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+
+    !integer.is_empty() && digits(integer) && digits(fraction)
+        && !(integer.len() > 1 && integer.starts_with('0'))
+        && !(unsigned.contains('.') && (fraction.is_empty() || fraction.ends_with('0')))
+        && integer.len() + fraction.len() <= 15
+}
+
+/// Parse a boolean cell value, as written by this or other spreadsheet applications.
+fn parse_boolean(value: &str) -> Option<bool> {
+    if value.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if value.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
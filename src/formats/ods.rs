@@ -0,0 +1,680 @@
+//! Reader and writer for OpenDocument spreadsheets (`.ods`).
+//!
+//! Tables are streamed from the archive's `content.xml`. When a spreadsheet opened from an OpenDocument file is saved
+//! as one again, the other tables and parts of the original file are carried over, so that editing one sheet of a
+//! workbook does not lose the rest.
+use formats::{is_plain_number, SAVE_PAGE_SIZE};
+use formats::excel::RowWriter;
+use formats::xml::attribute;
+use quick_xml::{Reader, Writer};
+use quick_xml::events::{BytesStart, Event};
+use Result;
+use spreadsheet::*;
+use std::cmp::min;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use zip::write::FileOptions;
+
+
+const MIME_TYPE: &'static str = "application/vnd.oasis.opendocument.spreadsheet";
+
+const MANIFEST: &'static str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+    r#"<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" "#,
+    r#"manifest:version="1.2">"#,
+    r#"<manifest:file-entry manifest:full-path="/" manifest:version="1.2" "#,
+    r#"manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>"#,
+    r#"<manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>"#,
+    r#"</manifest:manifest>"#,
+);
+
+const CONTENT_START: &'static str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+    r#"<office:document-content "#,
+    r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" "#,
+    r#"xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" "#,
+    r#"xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" "#,
+    r#"xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" "#,
+    r#"xmlns:number="urn:oasis:names:tc:opendocument:xmlns:datastyle:1.0" "#,
+    r#"xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" "#,
+    r#"office:version="1.2">"#,
+);
+
+/// Automatic styles used for the cells written by this module: a bold header row, and formats for dates.
+const STYLES: &'static str = concat!(
+    r#"<number:date-style style:name="AstinusDateFormat">"#,
+    r#"<number:year number:style="long"/><number:text>-</number:text>"#,
+    r#"<number:month number:style="long"/><number:text>-</number:text>"#,
+    r#"<number:day number:style="long"/>"#,
+    r#"</number:date-style>"#,
+    r#"<number:date-style style:name="AstinusDateTimeFormat">"#,
+    r#"<number:year number:style="long"/><number:text>-</number:text>"#,
+    r#"<number:month number:style="long"/><number:text>-</number:text>"#,
+    r#"<number:day number:style="long"/><number:text> </number:text>"#,
+    r#"<number:hours number:style="long"/><number:text>:</number:text>"#,
+    r#"<number:minutes number:style="long"/><number:text>:</number:text>"#,
+    r#"<number:seconds number:style="long"/>"#,
+    r#"</number:date-style>"#,
+    r#"<style:style style:name="AstinusHeader" style:family="table-cell">"#,
+    r#"<style:text-properties fo:font-weight="bold"/>"#,
+    r#"</style:style>"#,
+    r#"<style:style style:name="AstinusDate" style:family="table-cell" "#,
+    r#"style:data-style-name="AstinusDateFormat"/>"#,
+    r#"<style:style style:name="AstinusDateTime" style:family="table-cell" "#,
+    r#"style:data-style-name="AstinusDateTimeFormat"/>"#,
+);
+
+
+/// Get the names of the tables in an OpenDocument spreadsheet, in order.
+pub fn ods_sheets(path: &Path) -> Result<Vec<String>> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let mut reader = Reader::from_reader(BufReader::new(archive.by_name("content.xml")?));
+    let mut buf = Vec::new();
+    let mut sheets = Vec::new();
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name() == b"table" => {
+                sheets.push(attribute(&reader, e, b"name")?.unwrap_or_default());
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(sheets)
+}
+
+/// Load the first table of an OpenDocument spreadsheet into a spreadsheet.
+pub fn load_ods(path: &Path, spreadsheet: &Spreadsheet) -> Result<()> {
+    load_table(path, None, spreadsheet)
+}
+
+/// Load a table of an OpenDocument spreadsheet into a spreadsheet by name.
+pub fn load_ods_sheet(path: &Path, name: &str, spreadsheet: &Spreadsheet) -> Result<()> {
+    load_table(path, Some(name), spreadsheet)
+}
+
+/// Save a spreadsheet as an OpenDocument spreadsheet.
+///
+/// If the spreadsheet was opened from an OpenDocument file, the file is copied with the spreadsheet's table replaced
+/// by the current contents. Otherwise a new file with a single table is written. Numbers, dates, and booleans are
+/// written as typed cells, and the column names as a bold header row.
+pub fn save_ods(path: &Path, spreadsheet: &Spreadsheet) -> Result<()> {
+    let mut source = match spreadsheet.path() {
+        Some(source) if is_ods(source) && source.exists() => {
+            Some(ZipArchive::new(BufReader::new(File::open(source)?))?)
+        }
+        _ => None,
+    };
+
+    // Write to a temporary file first, since the original may be the file being replaced.
+    let temp_path = temp_path(path);
+    let result = File::create(&temp_path)
+        .map_err(Into::into)
+        .and_then(|file| write_ods(BufWriter::new(file), source.as_mut(), spreadsheet));
+
+    match result {
+        Ok(()) => {
+            drop(source);
+            fs::rename(&temp_path, path)?;
+            Ok(())
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Find a table and stream its rows into a spreadsheet. If no name is given, the first table is loaded.
+fn load_table(path: &Path, name: Option<&str>, spreadsheet: &Spreadsheet) -> Result<()> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let mut reader = Reader::from_reader(BufReader::new(archive.by_name("content.xml")?));
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) if e.local_name() == b"table" => {
+                let table_name = attribute(&reader, e, b"name")?.unwrap_or_default();
+
+                if name.map(|name| name == table_name).unwrap_or(true) {
+                    info!("loading table \"{}\"", table_name);
+                    return read_table(&mut reader, spreadsheet);
+                }
+            }
+            Event::Empty(ref e) if e.local_name() == b"table" => {
+                if name.is_none() || attribute(&reader, e, b"name")?.as_ref().map(String::as_str) == name {
+                    return Ok(());
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    match name {
+        Some(name) => Err(format!("The spreadsheet does not contain a table named \"{}\".", name).into()),
+        None => Err("The spreadsheet does not contain any tables.".into()),
+    }
+}
+
+/// Read the rows of the table the reader is positioned in, up to the end of the table.
+fn read_table<B: BufRead>(reader: &mut Reader<B>, spreadsheet: &Spreadsheet) -> Result<()> {
+    let mut buf = Vec::new();
+    let mut rows = RowWriter::new(spreadsheet)?;
+    let mut row_number = 1;
+    let mut row_repeat = 1;
+    let mut row = Vec::new();
+    let mut column = 0;
+    let mut cell = None;
+    let mut text = String::new();
+    let mut paragraphs = 0;
+    let mut in_paragraph = false;
+    let mut depth = 0;
+
+    // Comments attached to cells contain paragraphs too, which are not part of the value.
+    let mut annotation_depth = 0;
+
+    loop {
+        let event = reader.read_event(&mut buf)?;
+
+        match event {
+            Event::Start(ref e) => {
+                depth += 1;
+
+                match e.local_name() {
+                    _ if annotation_depth > 0 => annotation_depth += 1,
+                    b"annotation" => annotation_depth = 1,
+                    b"table-row" => {
+                        row_repeat = repeat_attribute(reader, e, b"number-rows-repeated")?;
+                        row.clear();
+                        column = 0;
+                    }
+                    b"table-cell" | b"covered-table-cell" => {
+                        cell = Some(cell_info(reader, e)?);
+                        text.clear();
+                        paragraphs = 0;
+                    }
+                    b"p" | b"h" if cell.is_some() => {
+                        if paragraphs > 0 {
+                            text.push('\n');
+                        }
+                        paragraphs += 1;
+                        in_paragraph = true;
+                    }
+                    b"s" if in_paragraph => push_spaces(reader, e, &mut text)?,
+                    _ => {}
+                }
+            }
+            Event::Empty(ref e) => match e.local_name() {
+                _ if annotation_depth > 0 => {}
+                b"table-row" => row_number += repeat_attribute(reader, e, b"number-rows-repeated")?,
+                b"table-cell" | b"covered-table-cell" => {
+                    let info = cell_info(reader, e)?;
+                    finish_cell(info, String::new(), &mut row, &mut column);
+                }
+                b"p" | b"h" if cell.is_some() => {
+                    if paragraphs > 0 {
+                        text.push('\n');
+                    }
+                    paragraphs += 1;
+                }
+                b"s" if in_paragraph => push_spaces(reader, e, &mut text)?,
+                b"tab" if in_paragraph => text.push('\t'),
+                b"line-break" if in_paragraph => text.push('\n'),
+                _ => {}
+            },
+            Event::Text(ref e) if in_paragraph && annotation_depth == 0 => {
+                text.push_str(&e.unescape_and_decode(reader)?);
+            }
+            Event::End(ref e) => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+
+                match e.local_name() {
+                    _ if annotation_depth > 0 => annotation_depth -= 1,
+                    b"p" | b"h" => in_paragraph = false,
+                    b"table-cell" | b"covered-table-cell" => if let Some(info) = cell.take() {
+                        finish_cell(info, mem::replace(&mut text, String::new()), &mut row, &mut column);
+                    },
+                    b"table-row" => {
+                        // Blank rows are counted but not written, since they are often repeated to the end of the
+                        // sheet.
+                        if row.iter().any(|value| !value.is_empty()) {
+                            for offset in 0..row_repeat {
+                                rows.write(row_number + offset, row.clone())?;
+                            }
+                        }
+                        row_number += row_repeat;
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    rows.finish()
+}
+
+/// Type and value of a cell being read.
+struct CellInfo {
+    /// Value stored in the cell's attributes for typed cells.
+    value: Option<String>,
+
+    /// Number of consecutive columns the cell fills.
+    repeat: usize,
+}
+
+/// Read the typed value and repeat count of a cell element.
+fn cell_info<B: BufRead>(reader: &Reader<B>, element: &BytesStart) -> Result<CellInfo> {
+    let kind = attribute(reader, element, b"value-type")?;
+
+    let value = match kind.as_ref().map(String::as_str) {
+        Some("float") | Some("percentage") | Some("currency") => attribute(reader, element, b"value")?,
+        Some("date") => attribute(reader, element, b"date-value")?.map(|value| format_date(&value)),
+        Some("time") => attribute(reader, element, b"time-value")?.and_then(|value| format_time(&value)),
+        Some("boolean") => attribute(reader, element, b"boolean-value")?.map(|value| match value.as_str() {
+            "true" => String::from("TRUE"),
+            _ => String::from("FALSE"),
+        }),
+        Some("string") => attribute(reader, element, b"string-value")?,
+        _ => None,
+    };
+
+    Ok(CellInfo {
+        value: value,
+        repeat: repeat_attribute(reader, element, b"number-columns-repeated")? as usize,
+    })
+}
+
+/// Add a finished cell to a row. Empty cells only advance the column, since they are often repeated to the end of
+/// the row.
+fn finish_cell(info: CellInfo, text: String, row: &mut Vec<String>, column: &mut usize) {
+    let value = info.value.unwrap_or(text);
+
+    if !value.is_empty() {
+        row.resize(*column, String::new());
+        for _ in 0..info.repeat {
+            row.push(value.clone());
+        }
+    }
+
+    *column += info.repeat;
+}
+
+/// Read a repeat count attribute, which defaults to 1.
+fn repeat_attribute<B: BufRead>(reader: &Reader<B>, element: &BytesStart, name: &[u8]) -> Result<u32> {
+    Ok(attribute(reader, element, name)?
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1)
+        .max(1))
+}
+
+/// Append the spaces represented by a `text:s` element.
+fn push_spaces<B: BufRead>(reader: &Reader<B>, element: &BytesStart, text: &mut String) -> Result<()> {
+    let count = repeat_attribute(reader, element, b"c")?;
+    for _ in 0..count {
+        text.push(' ');
+    }
+
+    Ok(())
+}
+
+/// Format a date value, such as `2021-01-01T12:00:00.5`, the same way dates are read from other workbooks.
+fn format_date(value: &str) -> String {
+    let value = value.split('.').next().unwrap_or(value);
+
+    match value.find('T') {
+        Some(index) if &value[index..] == "T00:00:00" => value[..index].to_string(),
+        Some(index) => format!("{} {}", &value[..index], &value[index + 1..]),
+        None => value.to_string(),
+    }
+}
+
+/// Format a time value given as an ISO 8601 duration, such as `PT12H30M00S`.
+fn format_time(value: &str) -> Option<String> {
+    let value = value.trim_left_matches('-').trim_left_matches("PT");
+    let mut fields = [0.0f64; 3];
+    let mut number = String::new();
+
+    for c in value.chars() {
+        match c {
+            'H' | 'M' | 'S' => {
+                let index = match c { 'H' => 0, 'M' => 1, _ => 2 };
+                fields[index] = number.parse().ok()?;
+                number.clear();
+            }
+            _ => number.push(c),
+        }
+    }
+
+    let seconds = (fields[0] * 3600.0 + fields[1] * 60.0 + fields[2]).round() as i64;
+    Some(format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60))
+}
+
+
+/// Write a complete OpenDocument archive, copying everything but the spreadsheet's table from the source if given.
+fn write_ods<W, R>(output: W, source: Option<&mut ZipArchive<R>>, spreadsheet: &Spreadsheet) -> Result<()>
+    where W: Write + Seek, R: Read + Seek
+{
+    let mut zip = ZipWriter::new(output);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The MIME type must come first and be stored uncompressed, so that it can be found by its offset.
+    zip.start_file("mimetype", FileOptions::default().compression_method(CompressionMethod::Stored))?;
+    zip.write_all(MIME_TYPE.as_bytes())?;
+
+    match source {
+        Some(source) => {
+            for index in 0..source.len() {
+                let mut file = source.by_index(index)?;
+                let name = file.name().to_string();
+
+                if name != "mimetype" && name != "content.xml" && !name.ends_with('/') {
+                    zip.start_file(name, options)?;
+                    io::copy(&mut file, &mut zip)?;
+                }
+            }
+
+            let content = BufReader::new(source.by_name("content.xml")?);
+            zip.start_file("content.xml", options)?;
+            copy_content(content, &mut zip, spreadsheet)?;
+        }
+        None => {
+            zip.start_file("META-INF/manifest.xml", options)?;
+            zip.write_all(MANIFEST.as_bytes())?;
+
+            zip.start_file("content.xml", options)?;
+            zip.write_all(CONTENT_START.as_bytes())?;
+            write!(zip, "<office:automatic-styles>{}</office:automatic-styles>", STYLES)?;
+            zip.write_all(b"<office:body><office:spreadsheet>")?;
+
+            let name = spreadsheet.sheet().unwrap_or("Sheet1");
+            write!(zip, r#"<table:table table:name="{}">"#, escape(name))?;
+            write_table(&mut zip, spreadsheet)?;
+            zip.write_all(b"</table:table></office:spreadsheet></office:body></office:document-content>")?;
+        }
+    }
+
+    zip.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// Copy the content of an existing document, replacing the spreadsheet's table with its current rows.
+///
+/// The table is matched by the name of the sheet the spreadsheet was opened from, or is the first table if it was
+/// opened without choosing a sheet. Styles written by an earlier save are replaced rather than added again.
+fn copy_content<R: BufRead, W: Write>(input: R, output: W, spreadsheet: &Spreadsheet) -> Result<()> {
+    let mut reader = Reader::from_reader(input);
+    let mut writer = Writer::new(output);
+    let mut buf = Vec::new();
+    let mut replaced = false;
+    let mut in_styles = false;
+
+    loop {
+        let event = reader.read_event(&mut buf)?;
+
+        match event {
+            Event::Start(ref e) if e.local_name() == b"table" && !replaced => {
+                let name = attribute(&reader, e, b"name")?;
+                if spreadsheet.sheet().map(|sheet| Some(sheet) == name.as_ref().map(String::as_str)).unwrap_or(true) {
+                    writer.write_event(&event)?;
+                    write_table(writer.inner(), spreadsheet)?;
+                    skip_element(&mut reader)?;
+                    writer.write_event(Event::End(e.to_end()))?;
+                    replaced = true;
+                } else {
+                    writer.write_event(&event)?;
+                }
+            }
+            Event::Start(ref e) if e.local_name() == b"automatic-styles" => {
+                in_styles = true;
+                writer.write_event(&event)?;
+            }
+            Event::Start(ref e) if in_styles && is_own_style(&reader, e)? => skip_element(&mut reader)?,
+            Event::Empty(ref e) if in_styles && is_own_style(&reader, e)? => {}
+            Event::Empty(ref e) if e.local_name() == b"automatic-styles" => {
+                writer.write_event(Event::Start(e.to_borrowed()))?;
+                writer.write(STYLES.as_bytes())?;
+                writer.write_event(Event::End(e.to_end()))?;
+            }
+            Event::End(ref e) if e.local_name() == b"automatic-styles" => {
+                in_styles = false;
+                writer.write(STYLES.as_bytes())?;
+                writer.write_event(&event)?;
+            }
+            Event::End(ref e) if e.local_name() == b"spreadsheet" && !replaced => {
+                // The table was not found, so add it to the end.
+                let name = spreadsheet.sheet().unwrap_or("Sheet1");
+                write!(writer.inner(), r#"<table:table table:name="{}">"#, escape(name))?;
+                write_table(writer.inner(), spreadsheet)?;
+                writer.write(b"</table:table>")?;
+                writer.write_event(&event)?;
+                replaced = true;
+            }
+            Event::Eof => break,
+            _ => writer.write_event(&event)?,
+        }
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+/// Check if an element is one of the styles in `STYLES`, left by an earlier save.
+fn is_own_style<B: BufRead>(reader: &Reader<B>, element: &BytesStart) -> Result<bool> {
+    match element.local_name() {
+        b"style" | b"date-style" => {
+            Ok(attribute(reader, element, b"name")?.map(|name| name.starts_with("Astinus")).unwrap_or(false))
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Skip over the contents of the element the reader is positioned in, including its end tag.
+fn skip_element<B: BufRead>(reader: &mut Reader<B>) -> Result<()> {
+    let mut buf = Vec::new();
+    let mut depth = 0;
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(_) => depth += 1,
+            Event::End(_) if depth == 0 => return Ok(()),
+            Event::End(_) => depth -= 1,
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Write the columns and rows of a table, starting with a header row of column names.
+fn write_table<W: Write>(output: &mut W, spreadsheet: &Spreadsheet) -> Result<()> {
+    let columns = spreadsheet.get_columns();
+
+    if !columns.is_empty() {
+        write!(output, r#"<table:table-column table:number-columns-repeated="{}"/>"#, columns.len())?;
+    }
+
+    let mut xml = String::from("<table:table-row>");
+    for name in &columns {
+        xml.push_str(r#"<table:table-cell table:style-name="AstinusHeader" office:value-type="string">"#);
+        push_paragraphs(&mut xml, name);
+        xml.push_str("</table:table-cell>");
+    }
+    xml.push_str("</table:table-row>");
+    output.write_all(xml.as_bytes())?;
+
+    let row_count = spreadsheet.get_row_count();
+    let mut start = 0;
+    while start < row_count {
+        let end = min(start + SAVE_PAGE_SIZE, row_count) - 1;
+
+        for row in spreadsheet.get_rows(start, end)? {
+            xml.clear();
+            xml.push_str("<table:table-row>");
+
+            // Leave out empty cells at the end of the row.
+            let length = row.iter().rposition(|value| value.as_ref().map(|v| !v.is_empty()).unwrap_or(false));
+            for value in row.iter().take(length.map(|i| i + 1).unwrap_or(0)) {
+                push_cell(&mut xml, value.as_ref().map(String::as_str).unwrap_or(""));
+            }
+
+            // A row must contain at least one cell.
+            if length.is_none() {
+                xml.push_str("<table:table-cell/>");
+            }
+
+            xml.push_str("</table:table-row>");
+            output.write_all(xml.as_bytes())?;
+        }
+
+        start = end + 1;
+    }
+
+    Ok(())
+}
+
+/// Append a cell, using a typed value if the text looks like a number, date, or boolean.
+fn push_cell(xml: &mut String, value: &str) {
+    if value.is_empty() {
+        xml.push_str("<table:table-cell/>");
+        return;
+    }
+
+    if is_plain_number(value) {
+        xml.push_str(&format!(r#"<table:table-cell office:value-type="float" office:value="{}">"#, value));
+    } else if is_date(value) {
+        xml.push_str(&format!(
+            r#"<table:table-cell table:style-name="AstinusDate" office:value-type="date" office:date-value="{}">"#,
+            value,
+        ));
+    } else if value.len() == 19 && is_date(&value[..10]) && is_time(&value[10..]) {
+        xml.push_str(&format!(
+            concat!(
+                r#"<table:table-cell table:style-name="AstinusDateTime" office:value-type="date" "#,
+                r#"office:date-value="{}T{}">"#,
+            ),
+            &value[..10],
+            &value[11..],
+        ));
+    } else if value == "TRUE" || value == "FALSE" {
+        xml.push_str(&format!(
+            r#"<table:table-cell office:value-type="boolean" office:boolean-value="{}">"#,
+            value.to_lowercase(),
+        ));
+    } else {
+        xml.push_str(r#"<table:table-cell office:value-type="string">"#);
+    }
+
+    push_paragraphs(xml, value);
+    xml.push_str("</table:table-cell>");
+}
+
+/// Append text as paragraphs, one per line.
+///
+/// Whitespace in paragraphs is collapsed when read, so runs of spaces and tabs are written as elements.
+fn push_paragraphs(xml: &mut String, text: &str) {
+    for line in text.split('\n') {
+        let line = line.trim_right_matches('\r');
+        let mut spaces = 0;
+
+        xml.push_str("<text:p>");
+
+        for (index, c) in line.char_indices() {
+            if c == ' ' {
+                spaces += 1;
+                continue;
+            }
+
+            let leading = index == spaces;
+            push_space_run(xml, &mut spaces, leading);
+
+            match c {
+                '\t' => xml.push_str("<text:tab/>"),
+                _ => push_escaped(xml, c.encode_utf8(&mut [0; 4])),
+            }
+        }
+
+        push_space_run(xml, &mut spaces, true);
+        xml.push_str("</text:p>");
+    }
+}
+
+/// Append a run of spaces. A single space between words can be written as-is, but spaces at the start or end of a
+/// paragraph and runs of more than one must be written as elements.
+fn push_space_run(xml: &mut String, spaces: &mut usize, edge: bool) {
+    match *spaces {
+        0 => {}
+        1 if !edge => xml.push(' '),
+        count if !edge => xml.push_str(&format!(r#" <text:s text:c="{}"/>"#, count - 1)),
+        count => xml.push_str(&format!(r#"<text:s text:c="{}"/>"#, count)),
+    }
+    *spaces = 0;
+}
+
+/// Check if text is a date of the form `YYYY-MM-DD`.
+fn is_date(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() == 10 && bytes[4] == b'-' && bytes[7] == b'-'
+        && bytes.iter().enumerate().all(|(i, b)| i == 4 || i == 7 || b.is_ascii_digit())
+}
+
+/// Check if text is a time preceded by a space, of the form ` HH:MM:SS`.
+fn is_time(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() == 9 && bytes[0] == b' ' && bytes[3] == b':' && bytes[6] == b':'
+        && bytes.iter().enumerate().skip(1).all(|(i, b)| i == 3 || i == 6 || b.is_ascii_digit())
+}
+
+/// Escape text for XML, dropping characters that XML cannot contain.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    push_escaped(&mut escaped, text);
+    escaped
+}
+
+/// Append text escaped for XML.
+fn push_escaped(escaped: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}' => {}
+            _ => escaped.push(c),
+        }
+    }
+}
+
+/// Check if a path has the OpenDocument spreadsheet extension.
+fn is_ods(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.eq_ignore_ascii_case("ods"))
+        .unwrap_or(false)
+}
+
+/// Get the path of a temporary file next to the given path.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+
+    path.with_file_name(name)
+}
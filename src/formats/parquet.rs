@@ -0,0 +1,80 @@
+//! Reader and writer for Apache Parquet files.
+use formats::columnar;
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use Result;
+use spreadsheet::*;
+use std::fs::File;
+use std::path::Path;
+
+
+/// Number of rows to decode from a row group at a time while loading.
+const LOAD_BATCH_SIZE: usize = 8192;
+
+
+/// Options for writing Parquet files.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParquetOptions {
+    /// Codec used to compress column data.
+    pub compression: ParquetCompression,
+
+    /// Maximum number of rows in each row group.
+    pub row_group_size: usize,
+}
+
+/// Codecs Parquet column data can be compressed with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParquetCompression {
+    Uncompressed,
+    Snappy,
+    Gzip,
+    Zstd,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        Self {
+            compression: ParquetCompression::Snappy,
+            row_group_size: 1024 * 1024,
+        }
+    }
+}
+
+
+/// Load a Parquet file into a spreadsheet.
+///
+/// Rows are decoded one row group at a time, so only a small part of a large file is ever in memory.
+pub fn load_parquet(path: &Path, spreadsheet: &Spreadsheet) -> Result<()> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    info!("loading {} row groups from {:?}", builder.metadata().num_row_groups(), path);
+
+    columnar::load_reader(builder.with_batch_size(LOAD_BATCH_SIZE).build()?, spreadsheet)
+}
+
+/// Save a spreadsheet as a Parquet file, with a physical type for each column inferred from its values or kept from the
+/// file it was loaded from.
+pub fn save_parquet(path: &Path, spreadsheet: &Spreadsheet, options: &ParquetOptions) -> Result<()> {
+    let schema = columnar::infer_schema(spreadsheet)?;
+
+    let compression = match options.compression {
+        ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+        ParquetCompression::Snappy => Compression::SNAPPY,
+        ParquetCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+        ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+    };
+    let properties = WriterProperties::builder()
+        .set_compression(compression)
+        .set_max_row_group_size(options.row_group_size)
+        .build();
+
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), Some(properties))?;
+    columnar::for_each_batch(spreadsheet, &schema, |batch| {
+        writer.write(&batch)?;
+        Ok(())
+    })?;
+    writer.close()?;
+
+    Ok(())
+}
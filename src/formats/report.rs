@@ -0,0 +1,114 @@
+//! Diagnostics about problems found in a file while loading it.
+use std::fmt;
+
+
+/// Maximum number of diagnostics to keep for a file. Any further problems are only counted.
+const MAX_DIAGNOSTICS: usize = 1000;
+
+
+/// Kinds of problems found in the records of a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DiagnosticKind {
+    /// The quoting of the record is broken, so its fields may not have been split where they were meant to be.
+    Malformed,
+
+    /// The record has a different number of fields than there are columns.
+    Ragged,
+
+    /// The record contains bytes that are not valid in the encoding of the file.
+    Unparseable,
+}
+
+/// A problem found on a line of a file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// Line the problem was found on, starting at 1.
+    pub line: u64,
+
+    /// Kind of problem.
+    pub kind: DiagnosticKind,
+
+    /// Description of the problem, and what was done about it.
+    pub message: String,
+}
+
+/// Problems found in a file while loading it, in line order.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LoadReport {
+    /// Problems found, up to a limit.
+    pub diagnostics: Vec<Diagnostic>,
+
+    /// Number of problems found beyond the limit.
+    pub omitted: usize,
+}
+
+impl LoadReport {
+    /// Record a problem found on a line.
+    pub fn add<S: Into<String>>(&mut self, line: u64, kind: DiagnosticKind, message: S) {
+        if self.diagnostics.len() < MAX_DIAGNOSTICS {
+            self.diagnostics.push(Diagnostic {
+                line: line,
+                kind: kind,
+                message: message.into(),
+            });
+        } else {
+            self.omitted += 1;
+        }
+    }
+
+    /// Check if no problems were found.
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Get the total number of problems found.
+    pub fn len(&self) -> usize {
+        self.diagnostics.len() + self.omitted
+    }
+
+    /// Get the lines that have a kind of problem.
+    pub fn lines(&self, kind: DiagnosticKind) -> Vec<u64> {
+        let mut lines = self.diagnostics.iter()
+            .filter(|diagnostic| diagnostic.kind == kind)
+            .map(|diagnostic| diagnostic.line)
+            .collect::<Vec<_>>();
+        lines.dedup();
+
+        lines
+    }
+
+    /// Put the problems in line order, since they may not be found in order.
+    pub fn sort(&mut self) {
+        self.diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DiagnosticKind::Malformed => "malformed",
+            DiagnosticKind::Ragged => "ragged",
+            DiagnosticKind::Unparseable => "unparseable",
+        })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {} record: {}", self.line, self.kind, self.message)
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{}", diagnostic)?;
+        }
+
+        if self.omitted > 0 {
+            writeln!(f, "And {} more problems.", self.omitted)?;
+        }
+
+        Ok(())
+    }
+}
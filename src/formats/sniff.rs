@@ -0,0 +1,303 @@
+//! Heuristics for guessing the dialect of a delimited text file from a sample of its contents.
+use csv;
+use formats::{detect_encoding, open_decompressed, Dialect, Terminator};
+use Result;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+
+/// Number of bytes to sample from the start of a file.
+pub const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Maximum number of records in the sample to inspect.
+const MAX_RECORDS: usize = 1000;
+
+/// Field delimiters to consider, in order of preference.
+const DELIMITERS: &'static [u8] = b",\t;|:";
+
+/// Quote characters to consider, in order of preference.
+const QUOTES: &'static [u8] = b"\"'";
+
+
+/// Guess the dialect and encoding of a delimited text file by sampling the beginning of it.
+///
+/// The fallback dialect is preferred when the sample is ambiguous, and is returned as-is if no delimiter can be
+/// detected at all.
+pub fn sniff_file(path: &Path, fallback: Dialect) -> Result<Dialect> {
+    sniff_reader(open_decompressed(path, None)?, fallback)
+}
+
+/// Guess the dialect and encoding of delimited text from a stream by sampling the beginning of it.
+pub fn sniff_reader<R: Read>(reader: R, fallback: Dialect) -> Result<Dialect> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    reader.take(SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+    let complete = sample.len() < SAMPLE_SIZE;
+
+    // Detect the encoding first, and inspect the rest of the sample as UTF-8.
+    let mut fallback = fallback;
+    let (encoding, bom) = detect_encoding(&sample, complete);
+    fallback.encoding = encoding;
+    fallback.bom = bom;
+
+    let (text, _) = encoding.decode_with_bom_removal(&sample);
+
+    Ok(sniff(text.as_bytes(), complete, fallback))
+}
+
+/// Guess the dialect of a sample of UTF-8 delimited text.
+///
+/// If `complete` is false, the sample is assumed to be truncated and its last partial line is ignored. Any lines the
+/// fallback dialect skips are ignored as well.
+pub fn sniff(sample: &[u8], complete: bool, fallback: Dialect) -> Dialect {
+    let sample = if complete {
+        sample
+    } else {
+        match sample.iter().rposition(|&b| b == b'\n' || b == b'\r') {
+            Some(end) => &sample[..end + 1],
+            None => sample,
+        }
+    };
+
+    // Ignore lines the caller has asked to skip.
+    let mut sample = sample;
+    for _ in 0..fallback.skip_lines {
+        match sample.iter().position(|&b| b == b'\n' || b == b'\r') {
+            Some(end) if sample[end] == b'\r' && sample.get(end + 1) == Some(&b'\n') => sample = &sample[end + 2..],
+            Some(end) => sample = &sample[end + 1..],
+            None => sample = &[],
+        }
+    }
+
+    let mut dialect = fallback;
+    dialect.terminator = sniff_terminator(sample).unwrap_or(fallback.terminator);
+    dialect.quote = sniff_quote(sample).unwrap_or(fallback.quote);
+    dialect.escape = sniff_escape(sample, dialect.quote);
+
+    match sniff_delimiter(sample, &dialect) {
+        Some(delimiter) => dialect.delimiter = delimiter,
+        None => return fallback,
+    }
+
+    dialect.has_headers = sniff_header(sample, &dialect);
+
+    dialect
+}
+
+/// Find the most common line ending in the sample.
+fn sniff_terminator(sample: &[u8]) -> Option<Terminator> {
+    let mut crlf = 0;
+    let mut lf = 0;
+    let mut cr = 0;
+
+    for (i, &byte) in sample.iter().enumerate() {
+        match byte {
+            b'\r' if sample.get(i + 1) == Some(&b'\n') => crlf += 1,
+            b'\r' => cr += 1,
+            b'\n' if i > 0 && sample[i - 1] == b'\r' => {}
+            b'\n' => lf += 1,
+            _ => {}
+        }
+    }
+
+    if crlf == 0 && lf == 0 && cr == 0 {
+        None
+    } else if crlf >= lf && crlf >= cr {
+        Some(Terminator::CRLF)
+    } else if lf >= cr {
+        Some(Terminator::LF)
+    } else {
+        Some(Terminator::CR)
+    }
+}
+
+/// Find the quote character that most often appears around whole fields.
+fn sniff_quote(sample: &[u8]) -> Option<u8> {
+    let mut best = None;
+    let mut best_count = 0;
+
+    for &quote in QUOTES {
+        let mut opening = 0;
+        let mut closing = 0;
+
+        for (i, &byte) in sample.iter().enumerate() {
+            if byte != quote {
+                continue;
+            }
+
+            if i == 0 || is_boundary(sample[i - 1]) {
+                opening += 1;
+            }
+
+            if sample.get(i + 1).map(|&b| is_boundary(b)).unwrap_or(true) {
+                closing += 1;
+            }
+        }
+
+        let count = min(opening, closing);
+        if count > best_count {
+            best = Some(quote);
+            best_count = count;
+        }
+    }
+
+    best
+}
+
+/// Determine whether quotes are escaped with a backslash rather than by doubling them.
+fn sniff_escape(sample: &[u8], quote: u8) -> Option<u8> {
+    let mut backslashed = 0;
+    let mut doubled = 0;
+
+    for i in 1..sample.len() {
+        if sample[i] != quote {
+            continue;
+        }
+
+        if sample[i - 1] == b'\\' {
+            backslashed += 1;
+        } else if sample[i - 1] == quote {
+            // An empty quoted field looks like a doubled quote, so only count quotes surrounded by field content.
+            let empty_field = (i < 2 || is_boundary(sample[i - 2]))
+                && sample.get(i + 1).map(|&b| is_boundary(b)).unwrap_or(true);
+
+            if !empty_field {
+                doubled += 1;
+            }
+        }
+    }
+
+    if backslashed > doubled {
+        Some(b'\\')
+    } else {
+        None
+    }
+}
+
+/// Find the delimiter that splits records into the most consistent number of fields.
+fn sniff_delimiter(sample: &[u8], dialect: &Dialect) -> Option<u8> {
+    // Try the fallback delimiter first so that it wins ties.
+    let mut candidates = vec![dialect.delimiter];
+    candidates.extend(DELIMITERS.iter().cloned().filter(|&d| d != dialect.delimiter));
+
+    let mut best = None;
+    let mut best_score = 0.0;
+
+    for delimiter in candidates {
+        let mut candidate = *dialect;
+        candidate.delimiter = delimiter;
+
+        let records = match parse_sample(sample, &candidate) {
+            Some(records) => records,
+            None => continue,
+        };
+
+        // Count how many records have each number of fields.
+        let mut frequencies = HashMap::new();
+        for record in &records {
+            *frequencies.entry(record.len()).or_insert(0) += 1;
+        }
+
+        let (fields, frequency) = frequencies.into_iter()
+            .max_by_key(|&(fields, frequency)| (frequency, fields))
+            .unwrap_or((0, 0));
+
+        if fields < 2 {
+            continue;
+        }
+
+        let score = frequency as f64 / records.len() as f64;
+        if score > best_score {
+            best = Some(delimiter);
+            best_score = score;
+        }
+    }
+
+    best
+}
+
+/// Guess whether the first record is a header by comparing it against the records that follow.
+///
+/// Each column gets a vote: if the values below the first record are all numeric or all the same length, and the
+/// first value does not fit that pattern, it is probably a column name.
+fn sniff_header(sample: &[u8], dialect: &Dialect) -> bool {
+    let records = match parse_sample(sample, dialect) {
+        Some(records) => records,
+        None => return dialect.has_headers,
+    };
+
+    if records.len() < 2 {
+        return dialect.has_headers;
+    }
+
+    let header = &records[0];
+    let mut votes = 0;
+
+    // Column names are almost never blank or repeated.
+    let unique: HashSet<_> = header.iter().collect();
+    if unique.len() < header.len() || header.iter().any(|name| name.trim().is_empty()) {
+        votes -= header.len() as i64;
+    }
+
+    for (column, name) in header.iter().enumerate() {
+        let values: Vec<&str> = records[1..].iter()
+            .filter_map(|record| record.get(column))
+            .map(|value| value.as_str())
+            .filter(|value| !value.is_empty())
+            .collect();
+
+        if values.is_empty() {
+            continue;
+        }
+
+        if values.iter().all(|value| is_numeric(value)) {
+            votes += if is_numeric(name) { -1 } else { 1 };
+        } else {
+            let length = values[0].chars().count();
+            if values.iter().all(|value| value.chars().count() == length) {
+                votes += if name.chars().count() == length { -1 } else { 1 };
+            }
+        }
+    }
+
+    votes >= 0
+}
+
+/// Parse the sample into records using the given dialect, returning `None` if it is not valid in that dialect.
+fn parse_sample(sample: &[u8], dialect: &Dialect) -> Option<Vec<Vec<String>>> {
+    let mut reader = csv::Reader::from_bytes(sample)
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .escape(dialect.escape)
+        .has_headers(false)
+        .flexible(true);
+
+    let mut records = Vec::new();
+
+    for record in reader.byte_records().take(MAX_RECORDS) {
+        let record = match record {
+            Ok(record) => record,
+            Err(_) => return None,
+        };
+
+        // Skip blank lines.
+        if record.len() == 1 && record[0].is_empty() {
+            continue;
+        }
+
+        records.push(record.into_iter().map(|field| String::from_utf8_lossy(&field).into_owned()).collect());
+    }
+
+    Some(records)
+}
+
+/// Check if a byte can appear directly next to a quote that starts or ends a field.
+fn is_boundary(byte: u8) -> bool {
+    byte == b'\r' || byte == b'\n' || byte == b' ' || DELIMITERS.contains(&byte)
+}
+
+/// Check if a value looks like a number.
+fn is_numeric(value: &str) -> bool {
+    value.trim().parse::<f64>().is_ok()
+}
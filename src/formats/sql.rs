@@ -0,0 +1,177 @@
+//! Writer for SQL scripts that create a table and insert the rows of a spreadsheet into it.
+use formats::{create_compressed, for_each_page, infer_column_types, parse_boolean, ColumnType};
+use Result;
+use spreadsheet::*;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+
+/// Database systems SQL scripts can be written for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SqlDialect {
+    Sqlite,
+    Postgres,
+    MySql,
+}
+
+/// Options for writing SQL scripts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SqlOptions {
+    /// Database system the script is written for.
+    pub dialect: SqlDialect,
+
+    /// Maximum number of rows inserted by each `INSERT` statement.
+    pub batch_size: usize,
+}
+
+impl Default for SqlOptions {
+    fn default() -> Self {
+        Self {
+            dialect: SqlDialect::Sqlite,
+            batch_size: 500,
+        }
+    }
+}
+
+impl SqlDialect {
+    /// Quote an identifier.
+    pub fn quote(self, name: &str) -> String {
+        match self {
+            SqlDialect::MySql => format!("`{}`", name.replace('`', "``")),
+            _ => format!("\"{}\"", name.replace('"', "\"\"")),
+        }
+    }
+
+    /// Get the type to declare a column holding values of the given type as.
+    pub fn column_type(self, column_type: ColumnType) -> &'static str {
+        match (self, column_type) {
+            (SqlDialect::Sqlite, ColumnType::Boolean) => "BOOLEAN",
+            (SqlDialect::Sqlite, ColumnType::Integer) => "INTEGER",
+            (SqlDialect::Sqlite, ColumnType::Float) => "REAL",
+            (SqlDialect::Postgres, ColumnType::Boolean) => "BOOLEAN",
+            (SqlDialect::Postgres, ColumnType::Integer) => "BIGINT",
+            (SqlDialect::Postgres, ColumnType::Float) => "DOUBLE PRECISION",
+            (SqlDialect::MySql, ColumnType::Boolean) => "BOOLEAN",
+            (SqlDialect::MySql, ColumnType::Integer) => "BIGINT",
+            (SqlDialect::MySql, ColumnType::Float) => "DOUBLE",
+            (_, ColumnType::Text) => "TEXT",
+        }
+    }
+
+    /// Get the literal for a cell value in a column of the given type. Empty cells are `NULL`.
+    fn literal(self, value: &str, column_type: ColumnType) -> String {
+        if value.is_empty() {
+            return String::from("NULL");
+        }
+
+        match (column_type, parse_boolean(value)) {
+            (ColumnType::Integer, _) | (ColumnType::Float, _) => value.to_string(),
+            (ColumnType::Boolean, Some(boolean)) => match self {
+                // SQLite has no boolean values, and stores them as integers instead.
+                SqlDialect::Sqlite => String::from(if boolean { "1" } else { "0" }),
+                _ => String::from(if boolean { "TRUE" } else { "FALSE" }),
+            },
+            _ => match self {
+                // MySQL treats backslashes in strings as escape characters by default.
+                SqlDialect::MySql => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "''")),
+                _ => format!("'{}'", value.replace('\'', "''")),
+            },
+        }
+    }
+
+    /// Get the statement that starts a transaction.
+    fn begin(self) -> &'static str {
+        match self {
+            SqlDialect::MySql => "START TRANSACTION;",
+            _ => "BEGIN;",
+        }
+    }
+}
+
+
+/// Save a spreadsheet as a SQL script that creates a table and inserts every row into it, in a single transaction.
+///
+/// Columns are declared with a type inferred from their values. A table needs a name and at least one column.
+pub fn save_sql(path: &Path, spreadsheet: &Spreadsheet, table: &str, options: &SqlOptions) -> Result<()> {
+    let dialect = options.dialect;
+    let columns = spreadsheet.get_columns();
+    check_table(table, &columns)?;
+
+    if options.batch_size == 0 {
+        return Err("Each INSERT statement must insert at least one row.".into());
+    }
+
+    let types = infer_column_types(spreadsheet)?;
+    let mut file = create_compressed(path)?;
+    let mut writer = BufWriter::new(&mut file);
+
+    writeln!(writer, "{}", dialect.begin())?;
+    writeln!(writer)?;
+
+    writeln!(writer, "CREATE TABLE {} (", dialect.quote(table))?;
+    for (index, (name, &column_type)) in columns.iter().zip(&types).enumerate() {
+        let separator = if index + 1 < columns.len() { "," } else { "" };
+        writeln!(writer, "    {} {}{}", dialect.quote(name), dialect.column_type(column_type), separator)?;
+    }
+    writeln!(writer, ");")?;
+
+    let insert = format!(
+        "INSERT INTO {} ({}) VALUES",
+        dialect.quote(table),
+        columns.iter().map(|name| dialect.quote(name)).collect::<Vec<_>>().join(", "),
+    );
+    let mut batch_rows = 0;
+
+    for_each_page(spreadsheet, |_, rows| {
+        for row in rows {
+            let values = types.iter()
+                .enumerate()
+                .map(|(index, &column_type)| match row.get(index) {
+                    Some(&Some(ref value)) => dialect.literal(value, column_type),
+                    _ => String::from("NULL"),
+                })
+                .collect::<Vec<_>>();
+
+            if batch_rows == 0 {
+                writeln!(writer)?;
+                writeln!(writer, "{}", insert)?;
+            } else {
+                writeln!(writer, ",")?;
+            }
+            write!(writer, "    ({})", values.join(", "))?;
+
+            batch_rows += 1;
+            if batch_rows == options.batch_size {
+                writeln!(writer, ";")?;
+                batch_rows = 0;
+            }
+        }
+
+        Ok(())
+    })?;
+
+    if batch_rows > 0 {
+        writeln!(writer, ";")?;
+    }
+
+    writeln!(writer)?;
+    writeln!(writer, "COMMIT;")?;
+    writer.flush()?;
+    drop(writer);
+    file.finish()?;
+
+    Ok(())
+}
+
+/// Check that a table can be created with the given name and columns.
+pub fn check_table(table: &str, columns: &[String]) -> Result<()> {
+    if table.is_empty() {
+        return Err("The table must have a name.".into());
+    }
+
+    if columns.is_empty() {
+        return Err("The spreadsheet has no columns to create a table with.".into());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,549 @@
+//! Reader and writer for tables in SQLite database files.
+use formats::{check_table, for_each_page, infer_column_types, parse_boolean, ColumnType, SqlDialect};
+use Result;
+use rusqlite::{self, Connection};
+use rusqlite::types::{ToSql, Value};
+use serde_json::{self, Value as JsonValue};
+use spreadsheet::*;
+use std::collections::HashSet;
+use std::path::Path;
+
+
+/// Get the names of the tables and views in a SQLite database, tables first.
+pub fn sqlite_tables(path: &Path) -> Result<Vec<String>> {
+    let connection = Connection::open_with_flags(path, rusqlite::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = connection.prepare("
+        SELECT name FROM sqlite_master
+        WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%'
+        ORDER BY type, rowid
+    ")?;
+
+    let mut tables = Vec::new();
+    let mut rows = stmt.query(&[])?;
+    while let Some(row) = rows.next() {
+        tables.push(row?.get(0));
+    }
+
+    Ok(tables)
+}
+
+/// Load a table or view of a SQLite database into a spreadsheet.
+///
+/// Each row of a table is given a key, either its rowid or its primary key, so that edits can be written back to the
+/// same row when saving. Keys keep the type of their values, so that they match the row whatever the column affinity.
+/// Views have no keys and cannot be written back to.
+pub fn load_sqlite_table(path: &Path, table: &str, spreadsheet: &Spreadsheet) -> Result<()> {
+    let connection = Connection::open_with_flags(path, rusqlite::SQLITE_OPEN_READ_ONLY)?;
+    let key_columns = key_columns(&connection, table)?.unwrap_or_default();
+
+    let mut selected = key_columns.iter().map(|name| quote(name)).collect::<Vec<_>>();
+    selected.push(String::from("*"));
+
+    let mut stmt = connection.prepare(&format!("SELECT {} FROM {}", selected.join(", "), quote(table)))?;
+    let key_count = key_columns.len();
+    let column_count = stmt.column_count() as usize;
+
+    let columns = stmt.column_names()[key_count..].iter().map(|name| name.to_string()).collect();
+    spreadsheet.insert_columns(InsertPosition::End, columns)?;
+
+    let mut inserter = spreadsheet.bulk_insert()?;
+    let mut rows = stmt.query(&[])?;
+    while let Some(row) = rows.next() {
+        let row = row?;
+        let values = row_text(&row, key_count, column_count)?;
+
+        if key_count > 0 {
+            inserter.push_with_key(values, &key_text(&row_values(&row, 0, key_count)?)?)?;
+        } else {
+            inserter.push(values)?;
+        }
+    }
+
+    inserter.finish()
+}
+
+/// Write the changes made to a spreadsheet back to the table it was loaded from.
+///
+/// Rows removed from the spreadsheet are deleted from the table, changed cells are updated in place, and new rows are
+/// inserted. Columns that are not in the table yet are added to it. Empty cells are written as `NULL`, except in new
+/// rows, where they take the default value of their column.
+pub fn save_sqlite_table(path: &Path, table: &str, spreadsheet: &Spreadsheet) -> Result<()> {
+    let mut connection = Connection::open(path)?;
+    let key_columns = match key_columns(&connection, table)? {
+        Some(key_columns) => key_columns,
+        None => return Err(format!("{} is a view, and cannot be saved to.", table).into()),
+    };
+
+    let transaction = connection.transaction()?;
+
+    // Add any columns that have been inserted into the spreadsheet.
+    let columns = spreadsheet.get_columns();
+    let table_columns = table_columns(&transaction, table)?;
+    for column in &columns {
+        if !table_columns.contains(column) {
+            transaction.execute(&format!("ALTER TABLE {} ADD COLUMN {}", quote(table), quote(column)), &[])?;
+        }
+    }
+
+    // Collect the keys of the rows still in the spreadsheet, so that the rest can be deleted first. Deleting first
+    // allows a row to be replaced by a new row with the same primary key.
+    let mut keys = HashSet::new();
+    for_each_page(spreadsheet, |start, rows| {
+        keys.extend(spreadsheet.get_row_keys(start, start + rows.len() as i64 - 1)?.into_iter().filter_map(|key| key));
+        Ok(())
+    })?;
+
+    let key_condition = key_columns.iter()
+        .map(|name| format!("{} = ?", quote(name)))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+
+    {
+        let mut select = transaction.prepare(&format!("SELECT {} FROM {}", quote_all(&key_columns), quote(table)))?;
+        let mut delete = transaction.prepare(&format!("DELETE FROM {} WHERE {}", quote(table), key_condition))?;
+        let mut removed = Vec::new();
+
+        let mut rows = select.query(&[])?;
+        while let Some(row) = rows.next() {
+            let key = row_values(&row?, 0, key_columns.len())?;
+
+            if !keys.contains(&key_text(&key)?) {
+                removed.push(key);
+            }
+        }
+
+        info!("deleting {} rows from {}", removed.len(), table);
+        for key in removed {
+            delete.execute(&params(&key))?;
+        }
+    }
+
+    // Find the columns of the spreadsheet that hold each key column. A column declared as `INTEGER PRIMARY KEY` is
+    // another name for the rowid, and changing it changes the rowid.
+    let key_sources = match rowid_alias(&transaction, table, &key_columns)? {
+        Some(alias) => vec![alias],
+        None => key_columns.clone(),
+    };
+    let key_sources = key_sources.iter()
+        .map(|name| columns.iter().position(|column| column == name))
+        .collect::<Option<Vec<_>>>();
+
+    // Update the rows that were loaded from the table, and insert the rest.
+    let mut new_keys = Vec::new();
+    let mut inserted_rows = Vec::new();
+    {
+        let mut select = transaction.prepare(&format!(
+            "SELECT {} FROM {} WHERE {}",
+            quote_all(&columns),
+            quote(table),
+            key_condition,
+        ))?;
+
+        // Finds the key of a row by the cells holding it, with the types the table stored them as.
+        let mut find_key = match key_sources {
+            Some(ref key_sources) => {
+                let condition = key_sources.iter()
+                    .map(|&index| format!("{} = ?", quote(&columns[index])))
+                    .collect::<Vec<_>>()
+                    .join(" AND ");
+
+                Some(transaction.prepare(&format!(
+                    "SELECT {} FROM {} WHERE {}",
+                    quote_all(&key_columns),
+                    quote(table),
+                    condition,
+                ))?)
+            }
+            None => None,
+        };
+
+        for_each_page(spreadsheet, |start, rows| {
+            let row_keys = spreadsheet.get_row_keys(start, start + rows.len() as i64 - 1)?;
+            for (offset, (row, key)) in rows.into_iter().zip(row_keys).enumerate() {
+                let values = (0..columns.len())
+                    .map(|index| row.get(index).cloned().and_then(|value| value).unwrap_or_default())
+                    .collect::<Vec<_>>();
+
+                let key = match key {
+                    Some(key) => key_values(&key)?,
+                    None => Vec::new(),
+                };
+
+                let current = if key.is_empty() {
+                    None
+                } else {
+                    match select.query(&params(&key))?.next() {
+                        Some(current) => Some(row_text(&current?, 0, columns.len())?),
+                        None => None,
+                    }
+                };
+
+                let (inserted, key_changed) = match current {
+                    Some(current) => {
+                        let changed = (0..columns.len())
+                            .filter(|&index| current[index] != values[index])
+                            .collect::<Vec<_>>();
+                        let key_changed = key_sources.as_ref()
+                            .map_or(false, |key_sources| key_sources.iter().any(|index| changed.contains(index)));
+
+                        update_row(&transaction, table, &columns, changed, &values, &key_condition, &key)?;
+                        (false, key_changed)
+                    }
+                    None => {
+                        insert_row(&transaction, table, &columns, &values)?;
+                        (true, true)
+                    }
+                };
+
+                // Find the key of the row as it is now, in case it was just inserted or its key was changed.
+                let new_key = if inserted && key_columns == ["rowid"] {
+                    vec![Value::Integer(transaction.last_insert_rowid())]
+                } else if key_changed {
+                    let found = match (find_key.as_mut(), key_sources.as_ref()) {
+                        (Some(find_key), Some(key_sources)) => {
+                            let cells = key_sources.iter().map(|&index| cell_value(&values[index])).collect::<Vec<_>>();
+                            match find_key.query(&params(&cells))?.next() {
+                                Some(found) => Some(row_values(&found?, 0, key_columns.len())?),
+                                None => None,
+                            }
+                        }
+                        _ => None,
+                    };
+                    found.unwrap_or_default()
+                } else {
+                    key.clone()
+                };
+
+                if new_key != key {
+                    new_keys.push((start + offset as i64, key_text(&new_key)?));
+                }
+
+                // Read inserted rows back, to show values generated by the database such as rowids and defaults.
+                if inserted && !new_key.is_empty() {
+                    if let Some(inserted) = select.query(&params(&new_key))?.next() {
+                        inserted_rows.push((start + offset as i64, values, row_text(&inserted?, 0, columns.len())?));
+                    }
+                }
+            }
+
+            Ok(())
+        })?;
+    }
+
+    transaction.commit()?;
+
+    for (row, key) in new_keys {
+        spreadsheet.set_row_key(row, Some(&key))?;
+    }
+
+    for (row, values, inserted) in inserted_rows {
+        for (column, (value, inserted)) in values.into_iter().zip(inserted).enumerate() {
+            if value != inserted {
+                spreadsheet.set_cell(row, column as i64, inserted)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Insert a row into a table. Empty cells are left out, so that they take the default value of their column.
+fn insert_row(connection: &Connection, table: &str, columns: &[String], values: &[String]) -> Result<()> {
+    let filled = (0..columns.len()).filter(|&index| !values[index].is_empty()).collect::<Vec<_>>();
+
+    let sql = if filled.is_empty() {
+        format!("INSERT INTO {} DEFAULT VALUES", quote(table))
+    } else {
+        format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            quote(table),
+            filled.iter().map(|&index| quote(&columns[index])).collect::<Vec<_>>().join(", "),
+            vec!["?"; filled.len()].join(", "),
+        )
+    };
+
+    let values = filled.iter().map(|&index| &values[index] as &ToSql).collect::<Vec<_>>();
+    connection.prepare_cached(&sql)?.execute(&values)?;
+
+    Ok(())
+}
+
+/// Append the rows of a spreadsheet to a table of a SQLite database, creating the database and table if they do not
+/// exist yet.
+///
+/// New tables declare each column with a type inferred from its values, and any columns missing from an existing table
+/// are added to it. Values are stored as the inferred type of their column, and empty cells as `NULL`.
+pub fn append_sqlite_table(path: &Path, table: &str, spreadsheet: &Spreadsheet) -> Result<()> {
+    let columns = spreadsheet.get_columns();
+    check_table(table, &columns)?;
+
+    let mut connection = Connection::open(path)?;
+    let types = infer_column_types(spreadsheet)?;
+
+    let transaction = connection.transaction()?;
+
+    match table_kind(&transaction, table)?.as_ref().map(String::as_str) {
+        Some("view") => return Err(format!("{} is a view, and cannot be saved to.", table).into()),
+        Some(_) => {
+            let table_columns = table_columns(&transaction, table)?;
+            for (column, &column_type) in columns.iter().zip(&types) {
+                if !table_columns.contains(column) {
+                    transaction.execute(&format!(
+                        "ALTER TABLE {} ADD COLUMN {} {}",
+                        quote(table),
+                        quote(column),
+                        SqlDialect::Sqlite.column_type(column_type),
+                    ), &[])?;
+                }
+            }
+        }
+        None => {
+            let definitions = columns.iter()
+                .zip(&types)
+                .map(|(column, &column_type)| {
+                    format!("{} {}", quote(column), SqlDialect::Sqlite.column_type(column_type))
+                })
+                .collect::<Vec<_>>();
+            transaction.execute(&format!("CREATE TABLE {} ({})", quote(table), definitions.join(", ")), &[])?;
+        }
+    }
+
+    {
+        let mut insert = transaction.prepare(&format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            quote(table),
+            quote_all(&columns),
+            vec!["?"; columns.len()].join(", "),
+        ))?;
+
+        for_each_page(spreadsheet, |_, rows| {
+            for row in rows {
+                let values = types.iter()
+                    .enumerate()
+                    .map(|(index, &column_type)| match row.get(index) {
+                        Some(&Some(ref value)) => typed_value(value, column_type),
+                        _ => Value::Null,
+                    })
+                    .collect::<Vec<_>>();
+
+                insert.execute(&values.iter().map(|value| value as &ToSql).collect::<Vec<_>>())?;
+            }
+
+            Ok(())
+        })?;
+    }
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+/// Update the changed cells of a row in a table.
+fn update_row(
+    connection: &Connection,
+    table: &str,
+    columns: &[String],
+    changed: Vec<usize>,
+    values: &[String],
+    key_condition: &str,
+    key: &[Value],
+) -> Result<()> {
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    let assignments = changed.iter()
+        .map(|&index| format!("{} = ?", quote(&columns[index])))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut values = changed.iter().map(|&index| cell_value(&values[index])).collect::<Vec<_>>();
+    values.extend(key.iter().cloned());
+
+    let sql = format!("UPDATE {} SET {} WHERE {}", quote(table), assignments, key_condition);
+    connection.prepare_cached(&sql)?.execute(&values.iter().map(|value| value as &ToSql).collect::<Vec<_>>())?;
+
+    Ok(())
+}
+
+/// Get the columns identifying each row of a table: the primary key of a table without rowids, or the rowid for any
+/// other table. Views have no key columns.
+fn key_columns(connection: &Connection, table: &str) -> Result<Option<Vec<String>>> {
+    match table_kind(connection, table)?.as_ref().map(String::as_str) {
+        Some("view") => return Ok(None),
+        Some(_) => {}
+        None => return Err(format!("The database does not contain a table named {}.", table).into()),
+    }
+
+    // Tables without rowids always have a primary key, and selecting their rowid fails.
+    if connection.prepare(&format!("SELECT rowid FROM {}", quote(table))).is_ok() {
+        return Ok(Some(vec![String::from("rowid")]));
+    }
+
+    let mut stmt = connection.prepare(&format!("PRAGMA table_info({})", quote(table)))?;
+    let mut rows = stmt.query(&[])?;
+    let mut primary_key = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        let position: i64 = row.get(5);
+        if position > 0 {
+            primary_key.push((position, row.get(1)));
+        }
+    }
+    primary_key.sort();
+
+    Ok(Some(primary_key.into_iter().map(|(_, name)| name).collect()))
+}
+
+/// Get the column declared as `INTEGER PRIMARY KEY` in a table keyed by rowid, which is another name for the rowid.
+fn rowid_alias(connection: &Connection, table: &str, key_columns: &[String]) -> Result<Option<String>> {
+    if key_columns != ["rowid"] {
+        return Ok(None);
+    }
+
+    let mut stmt = connection.prepare(&format!("PRAGMA table_info({})", quote(table)))?;
+    let mut rows = stmt.query(&[])?;
+    let mut primary_key = Vec::new();
+
+    while let Some(row) = rows.next() {
+        let row = row?;
+        let position: i64 = row.get(5);
+        if position > 0 {
+            primary_key.push((row.get::<_, String>(1), row.get::<_, String>(2)));
+        }
+    }
+
+    match primary_key.pop() {
+        Some((name, declared_type)) if primary_key.is_empty() && declared_type.eq_ignore_ascii_case("integer") => {
+            Ok(Some(name))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Get whether a name in a database is a table or a view, or nothing if there is neither.
+fn table_kind(connection: &Connection, table: &str) -> Result<Option<String>> {
+    let mut stmt = connection.prepare("SELECT type FROM sqlite_master WHERE name = ? AND type IN ('table', 'view')")?;
+    let mut rows = stmt.query(&[&table])?;
+
+    match rows.next() {
+        Some(row) => Ok(Some(row?.get(0))),
+        None => Ok(None),
+    }
+}
+
+/// Get the names of the columns of a table.
+fn table_columns(connection: &Connection, table: &str) -> Result<HashSet<String>> {
+    let mut stmt = connection.prepare(&format!("PRAGMA table_info({})", quote(table)))?;
+    let mut rows = stmt.query(&[])?;
+    let mut columns = HashSet::new();
+
+    while let Some(row) = rows.next() {
+        columns.insert(row?.get(1));
+    }
+
+    Ok(columns)
+}
+
+/// Get the text of a range of values in a result row.
+fn row_text(row: &rusqlite::Row, start: usize, end: usize) -> Result<Vec<String>> {
+    let mut values = Vec::with_capacity(end - start);
+    for index in start..end {
+        values.push(value_text(&row.get_checked(index as i32)?));
+    }
+
+    Ok(values)
+}
+
+/// Get a range of values in a result row.
+fn row_values(row: &rusqlite::Row, start: usize, end: usize) -> Result<Vec<Value>> {
+    let mut values = Vec::with_capacity(end - start);
+    for index in start..end {
+        values.push(row.get_checked(index as i32)?);
+    }
+
+    Ok(values)
+}
+
+/// Get the text of a value stored in SQLite. Blobs are shown as hexadecimal.
+fn value_text(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) => f.to_string(),
+        Value::Text(ref s) => s.clone(),
+        Value::Blob(ref bytes) => bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+    }
+}
+
+/// Get the value to store in SQLite for the text of a cell. The column affinity of the table converts numbers.
+fn cell_value(value: &str) -> Value {
+    if value.is_empty() {
+        Value::Null
+    } else {
+        Value::Text(value.to_string())
+    }
+}
+
+/// Get the value to store in SQLite for the text of a cell in a column of the given type. Booleans are stored as
+/// integers, like SQLite itself does.
+fn typed_value(value: &str, column_type: ColumnType) -> Value {
+    let typed = match column_type {
+        _ if value.is_empty() => Some(Value::Null),
+        ColumnType::Boolean => parse_boolean(value).map(|boolean| Value::Integer(boolean as i64)),
+        ColumnType::Integer => value.parse().ok().map(Value::Integer),
+        ColumnType::Float => value.parse().ok().map(Value::Real),
+        ColumnType::Text => None,
+    };
+
+    typed.unwrap_or_else(|| Value::Text(value.to_string()))
+}
+
+/// Get the text of a row key, as JSON that keeps the type of each value. Blobs are arrays of bytes.
+fn key_text(key: &[Value]) -> Result<String> {
+    let key = key.iter()
+        .map(|value| match *value {
+            Value::Null => JsonValue::Null,
+            Value::Integer(i) => JsonValue::from(i),
+            Value::Real(f) => JsonValue::from(f),
+            Value::Text(ref s) => JsonValue::from(s.as_str()),
+            Value::Blob(ref bytes) => JsonValue::from(bytes.clone()),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(serde_json::to_string(&key)?)
+}
+
+/// Get the values of a row key from its text.
+fn key_values(text: &str) -> Result<Vec<Value>> {
+    let key: Vec<JsonValue> = serde_json::from_str(text)?;
+
+    key.into_iter()
+        .map(|value| match value {
+            JsonValue::Null => Ok(Value::Null),
+            JsonValue::Number(ref n) if n.is_i64() => Ok(Value::Integer(n.as_i64().unwrap_or_default())),
+            JsonValue::Number(ref n) => Ok(Value::Real(n.as_f64().unwrap_or_default())),
+            JsonValue::String(s) => Ok(Value::Text(s)),
+            JsonValue::Array(bytes) => {
+                Ok(Value::Blob(bytes.iter().filter_map(JsonValue::as_u64).map(|byte| byte as u8).collect()))
+            }
+            _ => Err(format!("{} is not a row key.", text).into()),
+        })
+        .collect()
+}
+
+/// Get the parameters for a list of values.
+fn params(values: &[Value]) -> Vec<&ToSql> {
+    values.iter().map(|value| value as &ToSql).collect()
+}
+
+/// Quote a list of identifiers, separated by commas.
+fn quote_all(names: &[String]) -> String {
+    names.iter().map(|name| quote(name)).collect::<Vec<_>>().join(", ")
+}
+
+/// Quote an identifier for use in SQL.
+fn quote(name: &str) -> String {
+    SqlDialect::Sqlite.quote(name)
+}
@@ -0,0 +1,467 @@
+//! Reader for legacy Excel 97-2003 workbooks (`.xls`), stored as BIFF8 records inside a compound file.
+use byteorder::{ByteOrder, LittleEndian};
+use formats::cfb::CompoundFile;
+use formats::excel::{self, NumberKind, RowWriter};
+use Result;
+use spreadsheet::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+
+/// BIFF record types.
+const BOF: u16 = 0x0809;
+const EOF: u16 = 0x000A;
+const CONTINUE: u16 = 0x003C;
+const FILEPASS: u16 = 0x002F;
+const DATEMODE: u16 = 0x0022;
+const BOUNDSHEET: u16 = 0x0085;
+const SST: u16 = 0x00FC;
+const FORMAT: u16 = 0x041E;
+const XF: u16 = 0x00E0;
+const LABELSST: u16 = 0x00FD;
+const LABEL: u16 = 0x0204;
+const NUMBER: u16 = 0x0203;
+const RK: u16 = 0x027E;
+const MULRK: u16 = 0x00BD;
+const BOOLERR: u16 = 0x0205;
+const FORMULA: u16 = 0x0006;
+const STRING: u16 = 0x0207;
+
+/// BIFF version number of Excel 97 and later.
+const BIFF8: u16 = 0x0600;
+
+
+/// A worksheet in a workbook.
+struct Sheet {
+    name: String,
+
+    /// Offset of the worksheet's BOF record in the workbook stream.
+    offset: usize,
+}
+
+/// Workbook-wide information needed to read worksheets.
+struct Workbook {
+    sheets: Vec<Sheet>,
+
+    /// Whether dates are counted from 1904 instead of 1900.
+    date1904: bool,
+
+    shared_strings: Vec<String>,
+
+    /// How numbers are displayed for each cell style, by style index.
+    styles: Vec<NumberKind>,
+}
+
+
+/// Get the names of the worksheets in an XLS workbook, in order.
+pub fn xls_sheets(path: &Path) -> Result<Vec<String>> {
+    let stream = read_workbook_stream(path)?;
+    let workbook = read_workbook(&stream)?;
+
+    Ok(workbook.sheets.into_iter().map(|sheet| sheet.name).collect())
+}
+
+/// Load the first worksheet of an XLS workbook into a spreadsheet.
+pub fn load_xls(path: &Path, spreadsheet: &Spreadsheet) -> Result<()> {
+    let stream = read_workbook_stream(path)?;
+    let workbook = read_workbook(&stream)?;
+
+    match workbook.sheets.first() {
+        Some(sheet) => load_sheet(&stream, &workbook, sheet, spreadsheet),
+        None => Err("The workbook does not contain any worksheets.".into()),
+    }
+}
+
+/// Load a worksheet of an XLS workbook into a spreadsheet by name.
+pub fn load_xls_sheet(path: &Path, name: &str, spreadsheet: &Spreadsheet) -> Result<()> {
+    let stream = read_workbook_stream(path)?;
+    let workbook = read_workbook(&stream)?;
+
+    match workbook.sheets.iter().find(|sheet| sheet.name == name) {
+        Some(sheet) => load_sheet(&stream, &workbook, sheet, spreadsheet),
+        None => Err(format!("The workbook does not contain a worksheet named \"{}\".", name).into()),
+    }
+}
+
+/// Read the BIFF stream out of the compound file.
+fn read_workbook_stream(path: &Path) -> Result<Vec<u8>> {
+    let mut file = CompoundFile::open(BufReader::new(File::open(path)?))?;
+
+    if let Some(stream) = file.read_stream("Workbook")? {
+        return Ok(stream);
+    }
+
+    // Excel 5 and 95 name the stream differently.
+    if file.read_stream("Book")?.is_some() {
+        return Err("Workbooks from Excel 95 and earlier are not supported.".into());
+    }
+
+    Err("The file does not contain an Excel workbook.".into())
+}
+
+/// Read the list of worksheets and the shared tables used by them from the workbook globals.
+fn read_workbook(stream: &[u8]) -> Result<Workbook> {
+    let mut records = Records::new(stream, 0);
+
+    match records.next() {
+        Some((BOF, data)) if data.len() >= 2 && LittleEndian::read_u16(data) == BIFF8 => {}
+        Some((BOF, _)) => return Err("Workbooks from Excel 95 and earlier are not supported.".into()),
+        _ => return Err("The workbook stream is corrupt.".into()),
+    }
+
+    let mut workbook = Workbook {
+        sheets: Vec::new(),
+        date1904: false,
+        shared_strings: Vec::new(),
+        styles: Vec::new(),
+    };
+    let mut formats = HashMap::new();
+
+    while let Some((kind, data)) = records.next() {
+        match kind {
+            EOF => break,
+            FILEPASS => return Err("Password-protected workbooks are not supported.".into()),
+            DATEMODE if data.len() >= 2 => workbook.date1904 = LittleEndian::read_u16(data) == 1,
+            BOUNDSHEET if data.len() >= 8 => {
+                // Skip chart sheets and macro sheets.
+                if data[5] != 0 {
+                    continue;
+                }
+
+                let mut reader = RecordReader::new(vec![&data[6..]]);
+                let length = reader.read_u8() as usize;
+                workbook.sheets.push(Sheet {
+                    name: reader.read_string(length),
+                    offset: LittleEndian::read_u32(data) as usize,
+                });
+            }
+            SST if data.len() >= 8 => {
+                // The string table is usually too big for one record and continues in the records that follow.
+                let mut segments = vec![&data[8..]];
+                while let Some(data) = records.next_continue() {
+                    segments.push(data);
+                }
+
+                let count = LittleEndian::read_u32(&data[4..]) as usize;
+                let mut reader = RecordReader::new(segments);
+                while workbook.shared_strings.len() < count && !reader.is_empty() {
+                    let length = reader.read_u16() as usize;
+                    workbook.shared_strings.push(reader.read_string(length));
+                }
+            }
+            FORMAT if data.len() >= 4 => {
+                let id = LittleEndian::read_u16(data) as u32;
+                let mut reader = RecordReader::new(vec![&data[2..]]);
+                let length = reader.read_u16() as usize;
+                formats.insert(id, excel::format_code_kind(&reader.read_string(length)));
+            }
+            XF if data.len() >= 4 => {
+                let id = LittleEndian::read_u16(&data[2..]) as u32;
+                workbook.styles.push(formats.get(&id).cloned().unwrap_or_else(|| excel::builtin_format_kind(id)));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(workbook)
+}
+
+/// Read the cells of a worksheet into a spreadsheet.
+fn load_sheet(stream: &[u8], workbook: &Workbook, sheet: &Sheet, spreadsheet: &Spreadsheet) -> Result<()> {
+    info!("loading worksheet \"{}\"", sheet.name);
+
+    let mut records = Records::new(stream, sheet.offset);
+    match records.next() {
+        Some((BOF, _)) => {}
+        _ => return Err("The worksheet is corrupt.".into()),
+    }
+
+    // Cells are normally stored in order, but that is not guaranteed, so collect them before inserting any rows.
+    // A worksheet has at most 65536 rows and 256 columns.
+    let mut cells = BTreeMap::new();
+    let mut formula_cell = None;
+
+    while let Some((kind, data)) = records.next() {
+        let (row, column) = if data.len() >= 6 {
+            (LittleEndian::read_u16(data), LittleEndian::read_u16(&data[2..]))
+        } else {
+            (0, 0)
+        };
+        let style = |data: &[u8]| LittleEndian::read_u16(&data[4..]) as usize;
+
+        let value = match kind {
+            EOF => break,
+            LABELSST if data.len() >= 10 => {
+                let index = LittleEndian::read_u32(&data[6..]) as usize;
+                workbook.shared_strings.get(index).cloned().unwrap_or_default()
+            }
+            LABEL if data.len() >= 8 => {
+                let mut reader = RecordReader::new(vec![&data[6..]]);
+                let length = reader.read_u16() as usize;
+                reader.read_string(length)
+            }
+            NUMBER if data.len() >= 14 => {
+                format_number(LittleEndian::read_f64(&data[6..]), style(data), workbook)
+            }
+            RK if data.len() >= 10 => {
+                format_number(decode_rk(LittleEndian::read_u32(&data[6..])), style(data), workbook)
+            }
+            MULRK if data.len() >= 6 => {
+                // A run of RK values in consecutive columns of one row.
+                for (i, chunk) in data[4..data.len() - 2].chunks(6).enumerate() {
+                    if chunk.len() == 6 {
+                        let style = LittleEndian::read_u16(chunk) as usize;
+                        let value = format_number(decode_rk(LittleEndian::read_u32(&chunk[2..])), style, workbook);
+                        cells.entry(row).or_insert_with(BTreeMap::new).insert(column + i as u16, value);
+                    }
+                }
+                continue;
+            }
+            BOOLERR if data.len() >= 8 => match data[7] {
+                0 if data[6] != 0 => String::from("TRUE"),
+                0 => String::from("FALSE"),
+                _ => error_text(data[6]).to_string(),
+            },
+            FORMULA if data.len() >= 14 => {
+                // The cached result of the formula is either a number, or a type tag if the top bytes are 0xFFFF.
+                let result = &data[6..14];
+                if result[6] != 0xFF || result[7] != 0xFF {
+                    format_number(LittleEndian::read_f64(result), style(data), workbook)
+                } else {
+                    match result[0] {
+                        // The string result follows in a STRING record.
+                        0 => {
+                            formula_cell = Some((row, column));
+                            continue;
+                        }
+                        1 => String::from(if result[2] != 0 { "TRUE" } else { "FALSE" }),
+                        2 => error_text(result[2]).to_string(),
+                        _ => String::new(),
+                    }
+                }
+            }
+            STRING => match formula_cell.take() {
+                Some((row, column)) => {
+                    let mut segments = vec![data];
+                    while let Some(data) = records.next_continue() {
+                        segments.push(data);
+                    }
+
+                    let mut reader = RecordReader::new(segments);
+                    let length = reader.read_u16() as usize;
+                    let value = reader.read_string(length);
+                    cells.entry(row).or_insert_with(BTreeMap::new).insert(column, value);
+                    continue;
+                }
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        cells.entry(row).or_insert_with(BTreeMap::new).insert(column, value);
+    }
+
+    let mut rows = RowWriter::new(spreadsheet)?;
+    for (row, columns) in cells {
+        let mut values = Vec::new();
+        for (column, value) in columns {
+            values.resize(column as usize, String::new());
+            values.push(value);
+        }
+
+        rows.write(row as u32 + 1, values)?;
+    }
+
+    rows.finish()
+}
+
+/// Format a numeric cell value according to its style.
+fn format_number(value: f64, style: usize, workbook: &Workbook) -> String {
+    let kind = workbook.styles.get(style).cloned().unwrap_or(NumberKind::Number);
+    excel::format_number(value, kind, workbook.date1904)
+}
+
+/// Decode a compressed RK number, which is either a 30-bit integer or the top 30 bits of a double, optionally
+/// multiplied by 100.
+fn decode_rk(rk: u32) -> f64 {
+    let value = if rk & 0x02 != 0 {
+        (rk as i32 >> 2) as f64
+    } else {
+        f64::from_bits(((rk & 0xFFFF_FFFC) as u64) << 32)
+    };
+
+    if rk & 0x01 != 0 {
+        value / 100.0
+    } else {
+        value
+    }
+}
+
+/// Get the text Excel displays for an error code.
+fn error_text(code: u8) -> &'static str {
+    match code {
+        0x00 => "#NULL!",
+        0x07 => "#DIV/0!",
+        0x0F => "#VALUE!",
+        0x17 => "#REF!",
+        0x1D => "#NAME?",
+        0x24 => "#NUM!",
+        0x2A => "#N/A",
+        _ => "#ERROR!",
+    }
+}
+
+
+/// Iterates over the records of a BIFF stream.
+struct Records<'a> {
+    stream: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Records<'a> {
+    fn new(stream: &'a [u8], offset: usize) -> Self {
+        Self {
+            stream: stream,
+            offset: offset,
+        }
+    }
+
+    /// Get the type of the next record without consuming it.
+    fn peek_kind(&self) -> Option<u16> {
+        self.stream.get(self.offset..self.offset + 2).map(LittleEndian::read_u16)
+    }
+
+    /// Get the data of the next record if it is a CONTINUE record.
+    fn next_continue(&mut self) -> Option<&'a [u8]> {
+        match self.peek_kind() {
+            Some(CONTINUE) => self.next().map(|(_, data)| data),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.stream.get(self.offset..self.offset + 4)?;
+        let kind = LittleEndian::read_u16(header);
+        let length = LittleEndian::read_u16(&header[2..]) as usize;
+
+        let start = self.offset + 4;
+        let data = self.stream.get(start..start + length)?;
+        self.offset = start + length;
+
+        Some((kind, data))
+    }
+}
+
+
+/// Reads values from the data of a record and the CONTINUE records following it.
+struct RecordReader<'a> {
+    segments: Vec<&'a [u8]>,
+    segment: usize,
+    offset: usize,
+}
+
+impl<'a> RecordReader<'a> {
+    fn new(segments: Vec<&'a [u8]>) -> Self {
+        Self {
+            segments: segments,
+            segment: 0,
+            offset: 0,
+        }
+    }
+
+    /// Check if all of the data has been read.
+    fn is_empty(&mut self) -> bool {
+        self.current().is_empty()
+    }
+
+    /// Get the unread data in the current segment, moving on to the next segment if the current one is used up.
+    fn current(&mut self) -> &'a [u8] {
+        while self.segment < self.segments.len() && self.offset >= self.segments[self.segment].len() {
+            self.segment += 1;
+            self.offset = 0;
+        }
+
+        match self.segments.get(self.segment) {
+            Some(segment) => &segment[self.offset..],
+            None => &[],
+        }
+    }
+
+    fn read_u8(&mut self) -> u8 {
+        let value = self.current().first().cloned().unwrap_or(0);
+        self.offset += 1;
+        value
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        self.read_u8() as u16 | (self.read_u8() as u16) << 8
+    }
+
+    fn read_u32(&mut self) -> u32 {
+        self.read_u16() as u32 | (self.read_u16() as u32) << 16
+    }
+
+    fn skip(&mut self, mut count: usize) {
+        while count > 0 && !self.is_empty() {
+            let available = self.current().len().min(count);
+            self.offset += available;
+            count -= available;
+        }
+    }
+
+    /// Read a Unicode string with the given number of characters, which follows its length.
+    ///
+    /// Characters are stored either as single bytes or as UTF-16. When the characters are split across records, the
+    /// continuation starts with a new flags byte, since it may use a different width.
+    fn read_string(&mut self, length: usize) -> String {
+        let flags = self.read_u8();
+        let runs = if flags & 0x08 != 0 { self.read_u16() as usize } else { 0 };
+        let extended = if flags & 0x04 != 0 { self.read_u32() as usize } else { 0 };
+
+        let mut wide = flags & 0x01 != 0;
+        let mut units = Vec::with_capacity(length);
+        let mut segment = self.segment;
+
+        while units.len() < length {
+            let data = self.current();
+            if data.is_empty() {
+                break;
+            }
+
+            if self.segment != segment {
+                segment = self.segment;
+                wide = data[0] & 0x01 != 0;
+                self.offset += 1;
+                continue;
+            }
+
+            let width = if wide { 2 } else { 1 };
+            let count = (data.len() / width).min(length - units.len());
+
+            // A stray byte at the end of a segment cannot hold a whole character, so skip it rather than reading
+            // nothing forever.
+            if count == 0 {
+                self.offset += data.len();
+                continue;
+            }
+
+            if wide {
+                units.extend(data[..count * 2].chunks(2).map(LittleEndian::read_u16));
+            } else {
+                units.extend(data[..count].iter().map(|&b| b as u16));
+            }
+            self.offset += count * width;
+        }
+
+        // Skip formatting runs and phonetic data.
+        self.skip(runs * 4 + extended);
+
+        String::from_utf16_lossy(&units)
+    }
+}
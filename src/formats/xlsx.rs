@@ -0,0 +1,647 @@
+//! Reader and writer for Office Open XML workbooks (`.xlsx`).
+//!
+//! Worksheets are streamed straight from the archive into the spreadsheet and back; only the shared string table and
+//! cell styles are held in memory.
+use formats::{is_plain_number, SAVE_PAGE_SIZE};
+use formats::excel::{self, NumberKind, RowWriter};
+use formats::xml::attribute;
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use Result;
+use spreadsheet::*;
+use std::cmp::min;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::mem;
+use std::path::Path;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use zip::result::ZipError;
+use zip::write::FileOptions;
+
+
+/// Maximum number of rows in a worksheet.
+const MAX_ROWS: i64 = 1_048_576;
+
+/// Maximum number of columns in a worksheet.
+const MAX_COLUMNS: usize = 16_384;
+
+const CONTENT_TYPES: &'static str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+    r#"<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>"#,
+    r#"<Default Extension="xml" ContentType="application/xml"/>"#,
+    r#"<Override PartName="/xl/workbook.xml" "#,
+    r#"ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>"#,
+    r#"<Override PartName="/xl/worksheets/sheet1.xml" "#,
+    r#"ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+    r#"<Override PartName="/xl/styles.xml" "#,
+    r#"ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/>"#,
+    r#"</Types>"#,
+);
+
+const PACKAGE_RELATIONSHIPS: &'static str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    r#"<Relationship Id="rId1" "#,
+    r#"Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" "#,
+    r#"Target="xl/workbook.xml"/>"#,
+    r#"</Relationships>"#,
+);
+
+const WORKBOOK_RELATIONSHIPS: &'static str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    r#"<Relationship Id="rId1" "#,
+    r#"Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" "#,
+    r#"Target="worksheets/sheet1.xml"/>"#,
+    r#"<Relationship Id="rId2" "#,
+    r#"Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" "#,
+    r#"Target="styles.xml"/>"#,
+    r#"</Relationships>"#,
+);
+
+/// Styles for a workbook with two cell formats: the default, and bold text for the header row.
+const STYLES: &'static str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">"#,
+    r#"<fonts count="2">"#,
+    r#"<font><sz val="11"/><name val="Calibri"/><family val="2"/></font>"#,
+    r#"<font><b/><sz val="11"/><name val="Calibri"/><family val="2"/></font>"#,
+    r#"</fonts>"#,
+    r#"<fills count="2">"#,
+    r#"<fill><patternFill patternType="none"/></fill>"#,
+    r#"<fill><patternFill patternType="gray125"/></fill>"#,
+    r#"</fills>"#,
+    r#"<borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders>"#,
+    r#"<cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs>"#,
+    r#"<cellXfs count="2">"#,
+    r#"<xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/>"#,
+    r#"<xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/>"#,
+    r#"</cellXfs>"#,
+    r#"<cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles>"#,
+    r#"</styleSheet>"#,
+);
+
+/// Index of the bold cell style used for the header row.
+const HEADER_STYLE: usize = 1;
+
+
+/// A worksheet in a workbook.
+struct Sheet {
+    /// Name of the sheet as shown in Excel.
+    name: String,
+
+    /// Path of the worksheet XML within the archive.
+    path: String,
+}
+
+/// Workbook-wide information needed to read worksheets.
+struct Workbook {
+    sheets: Vec<Sheet>,
+
+    /// Whether dates are counted from 1904 instead of 1900.
+    date1904: bool,
+
+    shared_strings: Vec<String>,
+
+    /// How numbers are displayed for each cell style, by style index.
+    styles: Vec<NumberKind>,
+}
+
+/// Position and type of a cell being read.
+struct CellInfo {
+    column: usize,
+    kind: Option<String>,
+    style: usize,
+}
+
+
+/// Get the names of the worksheets in an XLSX workbook, in order.
+pub fn xlsx_sheets(path: &Path) -> Result<Vec<String>> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let (sheets, _) = read_sheets(&mut archive)?;
+
+    Ok(sheets.into_iter().map(|sheet| sheet.name).collect())
+}
+
+/// Save a spreadsheet as an XLSX workbook with a single worksheet.
+///
+/// The column names are written as a bold header row that is frozen, so that it stays in view while scrolling.
+/// Values that look like plain numbers are written as numbers, and everything else as text.
+pub fn save_xlsx(path: &Path, spreadsheet: &Spreadsheet) -> Result<()> {
+    let columns = spreadsheet.get_columns();
+    let row_count = spreadsheet.get_row_count();
+
+    if columns.len() > MAX_COLUMNS || row_count >= MAX_ROWS {
+        return Err("The spreadsheet is too large to save as an XLSX workbook.".into());
+    }
+
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("[Content_Types].xml", options)?;
+    zip.write_all(CONTENT_TYPES.as_bytes())?;
+
+    zip.start_file("_rels/.rels", options)?;
+    zip.write_all(PACKAGE_RELATIONSHIPS.as_bytes())?;
+
+    zip.start_file("xl/_rels/workbook.xml.rels", options)?;
+    zip.write_all(WORKBOOK_RELATIONSHIPS.as_bytes())?;
+
+    zip.start_file("xl/styles.xml", options)?;
+    zip.write_all(STYLES.as_bytes())?;
+
+    zip.start_file("xl/workbook.xml", options)?;
+    write!(
+        zip,
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" "#,
+            r#"xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+            r#"<sheets><sheet name="{}" sheetId="1" r:id="rId1"/></sheets>"#,
+            r#"</workbook>"#,
+        ),
+        escape(&sheet_name(spreadsheet.sheet().unwrap_or("Sheet1"))),
+    )?;
+
+    zip.start_file("xl/worksheets/sheet1.xml", options)?;
+    zip.write_all(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+        r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">"#,
+    ).as_bytes())?;
+
+    let references: Vec<String> = (0..columns.len()).map(column_name).collect();
+    if let Some(last) = references.last() {
+        write!(zip, r#"<dimension ref="A1:{}{}"/>"#, last, row_count + 1)?;
+    }
+
+    zip.write_all(concat!(
+        r#"<sheetViews><sheetView workbookViewId="0">"#,
+        r#"<pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/>"#,
+        r#"<selection pane="bottomLeft"/>"#,
+        r#"</sheetView></sheetViews>"#,
+        r#"<sheetData>"#,
+    ).as_bytes())?;
+
+    // Write the header row, then page through the rest.
+    let mut xml = String::new();
+    write_row(&mut xml, 1, &references, columns.iter().map(String::as_str), Some(HEADER_STYLE));
+    zip.write_all(xml.as_bytes())?;
+
+    let mut start = 0;
+    while start < row_count {
+        let end = min(start + SAVE_PAGE_SIZE, row_count) - 1;
+
+        for (offset, row) in spreadsheet.get_rows(start, end)?.iter().enumerate() {
+            let number = start + offset as i64 + 2;
+            let values = row.iter().map(|value| value.as_ref().map(String::as_str).unwrap_or(""));
+
+            xml.clear();
+            write_row(&mut xml, number, &references, values, None);
+            zip.write_all(xml.as_bytes())?;
+        }
+
+        start = end + 1;
+    }
+
+    zip.write_all(b"</sheetData></worksheet>")?;
+    zip.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// Load the first worksheet of an XLSX workbook into a spreadsheet.
+pub fn load_xlsx(path: &Path, spreadsheet: &Spreadsheet) -> Result<()> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let workbook = read_workbook(&mut archive)?;
+
+    match workbook.sheets.first() {
+        Some(sheet) => load_sheet(&mut archive, &workbook, sheet, spreadsheet),
+        None => Err("The workbook does not contain any worksheets.".into()),
+    }
+}
+
+/// Load a worksheet of an XLSX workbook into a spreadsheet by name.
+pub fn load_xlsx_sheet(path: &Path, name: &str, spreadsheet: &Spreadsheet) -> Result<()> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let workbook = read_workbook(&mut archive)?;
+
+    match workbook.sheets.iter().find(|sheet| sheet.name == name) {
+        Some(sheet) => load_sheet(&mut archive, &workbook, sheet, spreadsheet),
+        None => Err(format!("The workbook does not contain a worksheet named \"{}\".", name).into()),
+    }
+}
+
+/// Stream a worksheet from the archive into a spreadsheet.
+fn load_sheet<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    workbook: &Workbook,
+    sheet: &Sheet,
+    spreadsheet: &Spreadsheet,
+) -> Result<()> {
+    info!("loading worksheet \"{}\"", sheet.name);
+
+    let file = archive.by_name(&sheet.path)?;
+    read_sheet(BufReader::new(file), workbook, spreadsheet)
+}
+
+/// Read the list of worksheets and the shared tables used by them.
+fn read_workbook<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Workbook> {
+    let (sheets, date1904) = read_sheets(archive)?;
+
+    Ok(Workbook {
+        sheets: sheets,
+        date1904: date1904,
+        shared_strings: read_shared_strings(archive)?,
+        styles: read_styles(archive)?,
+    })
+}
+
+/// Read the list of worksheets, and whether the workbook uses the 1904 date system.
+fn read_sheets<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<(Vec<Sheet>, bool)> {
+    // Find the worksheet parts the workbook refers to by relationship ID.
+    let mut targets = HashMap::new();
+    {
+        let mut reader = Reader::from_reader(BufReader::new(archive.by_name("xl/_rels/workbook.xml.rels")?));
+        let mut buf = Vec::new();
+
+        loop {
+            match reader.read_event(&mut buf)? {
+                Event::Start(ref e) | Event::Empty(ref e) if e.local_name() == b"Relationship" => {
+                    let kind = attribute(&reader, e, b"Type")?.unwrap_or_default();
+                    let id = attribute(&reader, e, b"Id")?;
+                    let target = attribute(&reader, e, b"Target")?;
+
+                    if let (true, Some(id), Some(target)) = (kind.ends_with("/worksheet"), id, target) {
+                        // Targets are relative to the workbook part unless they are absolute.
+                        let path = if target.starts_with('/') {
+                            target[1..].to_string()
+                        } else {
+                            format!("xl/{}", target)
+                        };
+                        targets.insert(id, path);
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+
+    let mut sheets = Vec::new();
+    let mut date1904 = false;
+    {
+        let mut reader = Reader::from_reader(BufReader::new(archive.by_name("xl/workbook.xml")?));
+        let mut buf = Vec::new();
+
+        loop {
+            match reader.read_event(&mut buf)? {
+                Event::Start(ref e) | Event::Empty(ref e) => match e.local_name() {
+                    b"workbookPr" => {
+                        date1904 = match attribute(&reader, e, b"date1904")? {
+                            Some(ref value) => value == "1" || value == "true",
+                            None => false,
+                        };
+                    }
+                    b"sheet" => {
+                        let name = attribute(&reader, e, b"name")?.unwrap_or_default();
+                        let path = attribute(&reader, e, b"id")?.and_then(|id| targets.remove(&id));
+
+                        // Chart sheets have no worksheet part and are skipped.
+                        if let Some(path) = path {
+                            sheets.push(Sheet {
+                                name: name,
+                                path: path,
+                            });
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+
+    Ok((sheets, date1904))
+}
+
+/// Read the table of strings shared by all worksheets.
+fn read_shared_strings<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<String>> {
+    let file = match archive.by_name("xl/sharedStrings.xml") {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    let mut buf = Vec::new();
+    let mut strings = Vec::new();
+    let mut current = String::new();
+    let mut in_text = false;
+    let mut in_phonetic = false;
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) => match e.local_name() {
+                b"si" => current.clear(),
+                b"t" => in_text = !in_phonetic,
+                b"rPh" => in_phonetic = true,
+                _ => {}
+            },
+            Event::Empty(ref e) if e.local_name() == b"si" => strings.push(String::new()),
+            Event::Text(ref e) if in_text => current.push_str(&e.unescape_and_decode(&reader)?),
+            Event::End(ref e) => match e.local_name() {
+                b"si" => strings.push(unescape(&mem::replace(&mut current, String::new()))),
+                b"t" => in_text = false,
+                b"rPh" => in_phonetic = false,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(strings)
+}
+
+/// Read how numbers are displayed for each cell style, so that dates can be told apart from other numbers.
+fn read_styles<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<NumberKind>> {
+    let file = match archive.by_name("xl/styles.xml") {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    let mut buf = Vec::new();
+    let mut formats = HashMap::new();
+    let mut styles = Vec::new();
+    let mut in_cell_formats = false;
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) if e.local_name() == b"cellXfs" => in_cell_formats = true,
+            Event::End(ref e) if e.local_name() == b"cellXfs" => in_cell_formats = false,
+            Event::Start(ref e) | Event::Empty(ref e) => match e.local_name() {
+                b"numFmt" => {
+                    let id = attribute(&reader, e, b"numFmtId")?.and_then(|id| id.parse::<u32>().ok());
+                    let code = attribute(&reader, e, b"formatCode")?;
+
+                    if let (Some(id), Some(code)) = (id, code) {
+                        formats.insert(id, excel::format_code_kind(&code));
+                    }
+                }
+                b"xf" if in_cell_formats => {
+                    let id = attribute(&reader, e, b"numFmtId")?
+                        .and_then(|id| id.parse::<u32>().ok())
+                        .unwrap_or(0);
+
+                    styles.push(formats.get(&id).cloned().unwrap_or_else(|| excel::builtin_format_kind(id)));
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(styles)
+}
+
+/// Stream the rows of a worksheet into a spreadsheet.
+fn read_sheet<R: BufRead>(input: R, workbook: &Workbook, spreadsheet: &Spreadsheet) -> Result<()> {
+    let mut reader = Reader::from_reader(input);
+    let mut buf = Vec::new();
+    let mut rows = RowWriter::new(spreadsheet)?;
+    let mut row_number = 0;
+    let mut row = Vec::new();
+    let mut cell = None;
+    let mut value = String::new();
+    let mut in_value = false;
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) => match e.local_name() {
+                b"row" => {
+                    row_number = row_attribute(&reader, e)?.unwrap_or(row_number + 1);
+                    row.clear();
+                }
+                b"c" => {
+                    cell = Some(cell_info(&reader, e, row.len())?);
+                    value.clear();
+                }
+                b"v" | b"t" => in_value = cell.is_some(),
+                _ => {}
+            },
+            Event::Empty(ref e) if e.local_name() == b"row" => {
+                row_number = row_attribute(&reader, e)?.unwrap_or(row_number + 1);
+                rows.write(row_number, Vec::new())?;
+            }
+            Event::Text(ref e) if in_value => value.push_str(&e.unescape_and_decode(&reader)?),
+            Event::End(ref e) => match e.local_name() {
+                b"row" => rows.write(row_number, mem::replace(&mut row, Vec::new()))?,
+                b"c" => if let Some(cell) = cell.take() {
+                    while row.len() < cell.column {
+                        row.push(String::new());
+                    }
+                    row.push(cell_text(&cell, &value, workbook));
+                },
+                b"v" | b"t" => in_value = false,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    rows.finish()
+}
+
+/// Convert the raw value of a cell into text according to its type and style.
+fn cell_text(cell: &CellInfo, value: &str, workbook: &Workbook) -> String {
+    match cell.kind.as_ref().map(String::as_str) {
+        Some("s") => value.trim().parse::<usize>().ok()
+            .and_then(|index| workbook.shared_strings.get(index))
+            .cloned()
+            .unwrap_or_default(),
+        Some("b") => match value.trim() {
+            "1" => String::from("TRUE"),
+            "0" => String::from("FALSE"),
+            _ => String::new(),
+        },
+        Some("str") | Some("inlineStr") | Some("e") | Some("d") => unescape(value),
+        _ => match value.trim().parse::<f64>() {
+            Ok(number) => {
+                let kind = workbook.styles.get(cell.style).cloned().unwrap_or(NumberKind::Number);
+                excel::format_number(number, kind, workbook.date1904)
+            }
+            Err(_) => value.to_string(),
+        },
+    }
+}
+
+/// Read the position, type, and style of a cell element.
+fn cell_info<B: BufRead>(reader: &Reader<B>, element: &BytesStart, next_column: usize) -> Result<CellInfo> {
+    let column = attribute(reader, element, b"r")?
+        .and_then(|reference| column_index(&reference))
+        .unwrap_or(next_column);
+
+    let style = attribute(reader, element, b"s")?
+        .and_then(|style| style.parse().ok())
+        .unwrap_or(0);
+
+    Ok(CellInfo {
+        column: column,
+        kind: attribute(reader, element, b"t")?,
+        style: style,
+    })
+}
+
+/// Read the 1-based row number of a row element.
+fn row_attribute<B: BufRead>(reader: &Reader<B>, element: &BytesStart) -> Result<Option<u32>> {
+    Ok(attribute(reader, element, b"r")?.and_then(|r| r.parse().ok()))
+}
+
+/// Append the XML for a row of cells. Empty values are left out.
+fn write_row<'a, I>(xml: &mut String, number: i64, references: &[String], values: I, style: Option<usize>)
+    where I: Iterator<Item = &'a str>
+{
+    xml.push_str(&format!(r#"<row r="{}">"#, number));
+
+    for (reference, value) in references.iter().zip(values) {
+        if value.is_empty() {
+            continue;
+        }
+
+        xml.push_str(&format!(r#"<c r="{}{}""#, reference, number));
+        if let Some(style) = style {
+            xml.push_str(&format!(r#" s="{}""#, style));
+        }
+
+        if style.is_none() && is_plain_number(value) {
+            xml.push_str(&format!("><v>{}</v></c>", value));
+        } else if value.starts_with(char::is_whitespace) || value.ends_with(char::is_whitespace) {
+            xml.push_str(&format!(r#" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#, escape(value)));
+        } else {
+            xml.push_str(&format!(r#" t="inlineStr"><is><t>{}</t></is></c>"#, escape(value)));
+        }
+    }
+
+    xml.push_str("</row>");
+}
+
+/// Get the letters of a 0-based column index, such as `AB` for 27.
+fn column_name(index: usize) -> String {
+    let mut letters = Vec::new();
+    let mut index = index + 1;
+
+    while index > 0 {
+        letters.push(b'A' + ((index - 1) % 26) as u8);
+        index = (index - 1) / 26;
+    }
+
+    letters.reverse();
+    String::from_utf8(letters).unwrap()
+}
+
+/// Make a worksheet name acceptable to Excel, which limits names to 31 characters and forbids some punctuation.
+fn sheet_name(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+        .take(31)
+        .collect();
+
+    if name.trim().is_empty() {
+        String::from("Sheet1")
+    } else {
+        name
+    }
+}
+
+/// Escape text for XML. Characters that cannot appear in XML, and carriage returns that XML would turn into line
+/// feeds, are escaped as `_xHHHH_` the way Excel does.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for (index, c) in text.char_indices() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' => escaped.push(c),
+            '\u{0}'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}' => escaped.push_str(&format!("_x{:04X}_", c as u32)),
+
+            // Protect text that would otherwise be read as an escape sequence.
+            '_' if is_escape_sequence(&text[index..]) => escaped.push_str("_x005F_"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Check if text starts with an escape sequence of the form `_xHHHH_`.
+fn is_escape_sequence(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() >= 7 && bytes[1] == b'x' && bytes[6] == b'_' && bytes[2..6].iter().all(u8::is_ascii_hexdigit)
+}
+
+/// Get the 0-based column index of a cell reference such as `AB12`.
+fn column_index(reference: &str) -> Option<usize> {
+    let mut index = 0;
+    let mut letters = 0;
+
+    for c in reference.chars().take_while(|c| c.is_ascii_alphabetic()) {
+        index = index * 26 + (c.to_ascii_uppercase() as usize - 'A' as usize + 1);
+        letters += 1;
+    }
+
+    if letters > 0 {
+        Some(index - 1)
+    } else {
+        None
+    }
+}
+
+/// Decode characters that Excel escapes as `_xHHHH_`, such as carriage returns.
+fn unescape(text: &str) -> String {
+    if !text.contains("_x") {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("_x") {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.get(2..6)
+            .filter(|_| rest.get(6..7) == Some("_"))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(::std::char::from_u32);
+
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[7..];
+            }
+            None => {
+                result.push_str("_x");
+                rest = &rest[2..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
@@ -0,0 +1,23 @@
+//! XML helpers shared by the workbook formats.
+use quick_xml::Reader;
+use quick_xml::events::BytesStart;
+use Result;
+use std::io::BufRead;
+
+
+/// Get the unescaped value of an attribute by its local name.
+pub fn attribute<B: BufRead>(reader: &Reader<B>, element: &BytesStart, name: &[u8]) -> Result<Option<String>> {
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = match attribute.key.iter().position(|&b| b == b':') {
+            Some(colon) => &attribute.key[colon + 1..],
+            None => attribute.key,
+        };
+
+        if key == name {
+            return Ok(Some(attribute.unescape_and_decode_value(reader)?));
+        }
+    }
+
+    Ok(None)
+}
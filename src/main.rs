@@ -1,13 +1,30 @@
+extern crate arrow;
+extern crate byteorder;
+extern crate bzip2;
+extern crate chardetng;
 extern crate csv;
+extern crate encoding_rs;
+extern crate encoding_rs_io;
+extern crate flate2;
 extern crate gio;
 extern crate gtk;
 #[macro_use]
 extern crate log;
+extern crate memmap2;
+extern crate parquet;
+extern crate quick_xml;
 extern crate rusqlite;
+extern crate serde;
+extern crate serde_json;
 extern crate simplelog;
+extern crate xz2;
+extern crate zip;
+extern crate zstd;
 
 mod formats;
+mod progress;
 mod spreadsheet;
+mod storage;
 mod ui;
 
 use gtk::prelude::*;
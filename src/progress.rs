@@ -0,0 +1,143 @@
+//! Progress reporting and cancellation for spreadsheets loaded on a background thread.
+use Result;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+
+/// Progress of a spreadsheet being loaded on a background thread, shared with the thread displaying it.
+///
+/// A load is tracked by passing its progress to the spreadsheet being loaded, which the file formats and the storage
+/// report to.
+pub struct LoadProgress {
+    /// Size of the file being loaded, if known.
+    total_bytes: Option<u64>,
+
+    /// Number of bytes of the file read so far by the latest reader.
+    bytes: AtomicUsize,
+
+    /// Number of rows loaded so far.
+    rows: AtomicUsize,
+
+    /// Whether the load has been cancelled.
+    cancelled: AtomicBool,
+
+    /// Scratch database the spreadsheet is being loaded into, once it has been created.
+    database: Mutex<Option<PathBuf>>,
+}
+
+impl LoadProgress {
+    /// Create the progress of loading a number of bytes, if known.
+    pub fn new(total_bytes: Option<u64>) -> Self {
+        Self {
+            total_bytes: total_bytes,
+            bytes: AtomicUsize::new(0),
+            rows: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+            database: Mutex::new(None),
+        }
+    }
+
+    /// Create the progress of loading a file.
+    pub fn for_file(path: &Path) -> Self {
+        Self::new(fs::metadata(path).ok().map(|metadata| metadata.len()))
+    }
+
+    /// Get the number of bytes of the file read so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed) as u64
+    }
+
+    /// Get the fraction of the file read so far, if it can be measured.
+    ///
+    /// Formats that are not read as a stream do not report the bytes they read, so their progress cannot be measured.
+    pub fn fraction(&self) -> Option<f64> {
+        match (self.bytes(), self.total_bytes) {
+            (0, _) | (_, None) | (_, Some(0)) => None,
+            (bytes, Some(total)) => Some((bytes as f64 / total as f64).min(1.0)),
+        }
+    }
+
+    /// Get the number of rows loaded so far.
+    pub fn rows(&self) -> i64 {
+        self.rows.load(Ordering::Relaxed) as i64
+    }
+
+    /// Ask the load to stop. It stops the next time it reads from the file or loads a row.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Check if the load has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fail if the load has been cancelled, so that the loader stops.
+    pub fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err("Loading was cancelled.".into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the scratch database the spreadsheet is being loaded into, once it has been created.
+    pub fn database(&self) -> Option<PathBuf> {
+        self.database.lock().unwrap().clone()
+    }
+
+    /// Record the scratch database the spreadsheet is being loaded into.
+    pub fn set_database(&self, path: &Path) {
+        *self.database.lock().unwrap() = Some(path.to_path_buf());
+    }
+
+    /// Record how far into the file the load has read. The file may be read more than once, such as to guess its
+    /// format before loading it, so this can go back to the start.
+    pub fn set_bytes(&self, bytes: usize) {
+        self.bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Count rows as loaded.
+    pub fn add_rows(&self, count: usize) {
+        self.rows.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+
+/// Reader that counts the bytes read from it towards the progress of a load, if any, and stops reading if the load is
+/// cancelled.
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Option<Arc<LoadProgress>>,
+    bytes: usize,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, progress: Option<&Arc<LoadProgress>>) -> Self {
+        Self {
+            inner: inner,
+            progress: progress.cloned(),
+            bytes: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+
+        if let Some(ref progress) = self.progress {
+            if progress.is_cancelled() {
+                return Err(io::Error::new(io::ErrorKind::Other, "Loading was cancelled."));
+            }
+
+            self.bytes += count;
+            progress.set_bytes(self.bytes);
+        }
+
+        Ok(count)
+    }
+}
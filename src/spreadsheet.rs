@@ -1,10 +1,15 @@
 //! Spreadsheet file handling and processing.
-use formats;
+use encoding_rs::Encoding;
+use formats::{self, ColumnNaming, Dialect, FixedWidthLayout, LoadReport};
+use formats::{JsonLayout, JsonOptions, ParquetOptions, SqlOptions};
+use progress::LoadProgress;
 use Result;
-use rusqlite::Connection;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::cmp::{max, min};
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use storage::{BulkWriter, IndexedStorage, SqliteStorage, Storage};
 
 
 /// A loaded spreadsheet file. Provides methods for loading, saving, reading, and editing.
@@ -12,14 +17,31 @@ pub struct Spreadsheet {
     /// The name of the spreadsheet.
     name: String,
 
-    /// Open SQLite database for storing spreadsheet data.
-    database: Connection,
+    /// Path of the file the spreadsheet was opened from, if any.
+    path: Option<PathBuf>,
+
+    /// Name of the worksheet the spreadsheet was opened from, if it was opened from a workbook by name.
+    sheet: Option<String>,
+
+    /// Dialect used when reading or writing the spreadsheet as delimited text.
+    dialect: Cell<Dialect>,
+
+    /// Whether the spreadsheet was loaded from delimited or fixed-width text, so that it can be saved back as text
+    /// under the extension of its file even if the extension is not a known delimited text one.
+    text: bool,
+
+    /// Problems found in the file while loading the spreadsheet.
+    report: RefCell<LoadReport>,
+
+    /// Storage holding the columns and rows.
+    storage: Box<Storage>,
+
+    /// Progress of the load filling the spreadsheet, if it was loaded on a background thread. Reading the file and
+    /// adding rows in bulk report to it, and stop once it is cancelled.
+    loading: Option<Arc<LoadProgress>>,
 
     /// Whether the spreadhseet has been modified.
     dirty: Cell<bool>,
-
-    /// Number of rows in the spreadsheet.
-    row_count: Cell<i64>,
 }
 
 /// Position for inserting values at.
@@ -30,66 +52,405 @@ pub enum InsertPosition {
 }
 
 impl Spreadsheet {
-    /// Create a new, blank spreadsheet.
+    /// Create a new, blank spreadsheet, stored in a scratch database.
     pub fn new() -> Self {
-        // Open an on-disk, temporary scratch database.
-        let connection = Connection::open("").unwrap();
-
-        // Set up the schema.
-        connection.execute_batch("
-            CREATE TABLE columns (
-                id          INTEGER PRIMARY KEY NOT NULL,
-                name        TEXT NOT NULL
-            );
-
-            CREATE TABLE cells (
-                column      INTEGER NOT NULL,
-                row         INTEGER NOT NULL,
-                value       TEXT
-            );
-        ").unwrap();
+        Self::with_storage(Box::new(SqliteStorage::new()))
+    }
 
+    /// Create a new spreadsheet that holds its columns and rows in the given storage.
+    pub fn with_storage(storage: Box<Storage>) -> Self {
         Self {
             name: String::from("Untitled"),
-            database: connection,
+            path: None,
+            sheet: None,
+            dialect: Cell::new(Dialect::default()),
+            text: false,
+            report: RefCell::new(LoadReport::default()),
+            storage: storage,
+            loading: None,
             dirty: Cell::new(false),
-            row_count: Cell::new(0),
         }
     }
 
-    /// Open a spreadsheet from a file.
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+    /// Create a new, blank spreadsheet to load a file into, tracking the progress of the load if it runs on a
+    /// background thread.
+    ///
+    /// A spreadsheet loaded in the background is stored in a named scratch database, so that the rows loaded so far
+    /// can be browsed with `open_partial`.
+    fn for_load(progress: Option<&Arc<LoadProgress>>) -> Self {
+        let mut spreadsheet = match progress {
+            Some(progress) => Self::with_storage(Box::new(SqliteStorage::for_load(progress))),
+            None => Self::new(),
+        };
+        spreadsheet.loading = progress.cloned();
+
+        spreadsheet
+    }
+
+    /// Open a view of a spreadsheet that is still being loaded on another thread, to browse the rows loaded so far.
+    ///
+    /// Returns `None` if the load has not created the spreadsheet yet. The view must not be edited.
+    pub fn open_partial(progress: &Arc<LoadProgress>) -> Result<Option<Self>> {
+        Ok(SqliteStorage::open_partial(progress)?.map(|storage| {
+            let mut spreadsheet = Self::with_storage(Box::new(storage));
+            spreadsheet.name = String::from("Loading");
+            spreadsheet
+        }))
+    }
+
+    /// Open a spreadsheet from a file. Only the first worksheet of a workbook is opened.
+    ///
+    /// Text files compressed with gzip, zstd, bzip2, or xz are decompressed as they are read. The format is given by
+    /// the extension before the compressed file extension, as in `data.csv.gz`.
+    ///
+    /// A load running on a background thread reports to its progress, if given, and stops once it is cancelled.
+    pub fn open<P: AsRef<Path>>(path: P, progress: Option<&Arc<LoadProgress>>) -> Result<Self> {
         let path = path.as_ref();
 
-        let loader = match path.extension().and_then(|s| s.to_str()) {
-            Some("csv") => formats::load_csv,
-            _ => return Err("Unknown file extension.".into()),
+        let loader = match formats::uncompressed_extension(path).as_ref().map(String::as_str) {
+            Some("arrow") | Some("feather") | Some("ipc") => formats::load_ipc,
+            Some("db") | Some("sqlite") | Some("sqlite3") => {
+                check_uncompressed(path)?;
+
+                return match formats::sqlite_tables(path)?.first() {
+                    Some(table) => Self::open_sheet(path, table, progress),
+                    None => Err("The database does not contain any tables.".into()),
+                };
+            }
+            Some("json") | Some("jsonl") | Some("ndjson") => {
+                return Self::open_json(path, JsonOptions::default(), progress);
+            }
+            Some("ods") => formats::load_ods,
+            Some("parquet") => formats::load_parquet,
+            Some("xls") => formats::load_xls,
+            Some("xlsx") | Some("xlsm") => formats::load_xlsx,
+            Some("zip") => {
+                check_uncompressed(path)?;
+
+                return match formats::archive_members(path)?.first() {
+                    Some(member) => Self::open_sheet(path, member, progress),
+                    None => Err("The archive does not contain any delimited text files.".into()),
+                };
+            }
+            _ => return match Dialect::for_path(path) {
+                Some(dialect) => {
+                    let dialect = formats::sniff_file(path, dialect)?;
+                    info!("detected dialect for {:?}: {}", path, dialect);
+
+                    Self::open_delimited(path, dialect, progress)
+                }
+                None => Err("Unknown file extension.".into()),
+            },
         };
 
-        let mut spreadsheet = Self::new();
+        check_uncompressed(path)?;
+
+        let mut spreadsheet = Self::for_load(progress);
         loader(path, &spreadsheet)?;
+        spreadsheet.set_path(path);
 
-        if let Some(file_name) = path.file_name() {
-            spreadsheet.name = format!("{}", file_name.to_string_lossy());
+        Ok(spreadsheet)
+    }
+
+    /// Check if a file may hold delimited or fixed-width text, given its extension. Files with the extension of another
+    /// format do not, but files with an unknown extension might.
+    pub fn is_text<P: AsRef<Path>>(path: P) -> bool {
+        match formats::uncompressed_extension(path.as_ref()).as_ref().map(String::as_str) {
+            Some("arrow") | Some("feather") | Some("ipc") => false,
+            Some("db") | Some("sqlite") | Some("sqlite3") => false,
+            Some("json") | Some("jsonl") | Some("ndjson") => false,
+            Some("ods") | Some("parquet") | Some("zip") => false,
+            Some("xls") | Some("xlsx") | Some("xlsm") => false,
+            _ => true,
         }
-        spreadsheet.clear_dirty();
+    }
+
+    /// Get the names of the worksheets in a workbook file, in order.
+    ///
+    /// The tables of a SQLite database and the delimited text files in a ZIP archive are treated as worksheets. Files
+    /// that are not workbooks, such as delimited text, have no worksheets.
+    pub fn sheets<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+        let path = path.as_ref();
+
+        let sheets = match formats::uncompressed_extension(path).as_ref().map(String::as_str) {
+            Some("db") | Some("sqlite") | Some("sqlite3") => formats::sqlite_tables,
+            Some("ods") => formats::ods_sheets,
+            Some("xls") => formats::xls_sheets,
+            Some("xlsx") | Some("xlsm") => formats::xlsx_sheets,
+            Some("zip") => formats::archive_members,
+            _ => return Ok(Vec::new()),
+        };
+
+        check_uncompressed(path)?;
+        sheets(path)
+    }
+
+    /// Open a worksheet of a workbook file by name.
+    pub fn open_sheet<P: AsRef<Path>>(path: P, sheet: &str, progress: Option<&Arc<LoadProgress>>) -> Result<Self> {
+        let path = path.as_ref();
+
+        let loader = match formats::uncompressed_extension(path).as_ref().map(String::as_str) {
+            Some("db") | Some("sqlite") | Some("sqlite3") => formats::load_sqlite_table,
+            Some("ods") => formats::load_ods_sheet,
+            Some("xls") => formats::load_xls_sheet,
+            Some("xlsx") | Some("xlsm") => formats::load_xlsx_sheet,
+            Some("zip") => formats::load_archive_member,
+            _ => return Err("The file is not a workbook.".into()),
+        };
+
+        check_uncompressed(path)?;
+
+        let mut spreadsheet = Self::for_load(progress);
+        loader(path, sheet, &spreadsheet)?;
+        spreadsheet.set_path(path);
+        spreadsheet.name = format!("{} - {}", spreadsheet.name, sheet);
+        spreadsheet.sheet = Some(sheet.to_string());
+
+        Ok(spreadsheet)
+    }
+
+    /// Open a delimited text file using the given dialect.
+    pub fn open_delimited<P>(path: P, dialect: Dialect, progress: Option<&Arc<LoadProgress>>) -> Result<Self>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+
+        let mut spreadsheet = Self::for_load(progress);
+        let report = formats::load_delimited(path, &spreadsheet, &dialect)?;
+        spreadsheet.dialect.set(dialect);
+        spreadsheet.text = true;
+        spreadsheet.set_load_report(report);
+        spreadsheet.set_path(path);
+
+        Ok(spreadsheet)
+    }
+
+    /// Open a delimited text file for viewing in place, without importing it. Rows can be browsed while the file is
+    /// still being indexed in the background.
+    ///
+    /// Cells can be edited, but rows cannot be added or removed. Compressed files cannot be viewed in place.
+    pub fn open_indexed<P: AsRef<Path>>(path: P, dialect: Dialect) -> Result<Self> {
+        let path = path.as_ref();
+        check_uncompressed(path)?;
+
+        let mut spreadsheet = Self::with_storage(Box::new(IndexedStorage::open(path, &dialect)?));
+        spreadsheet.dialect.set(dialect);
+        spreadsheet.text = true;
+        spreadsheet.set_path(path);
+
+        Ok(spreadsheet)
+    }
+
+    /// Open a fixed-width text file using the given column layout.
+    ///
+    /// The spreadsheet is saved as delimited text in the file's encoding, since it cannot be written back in columns.
+    pub fn open_fixed_width<P>(path: P, layout: FixedWidthLayout, progress: Option<&Arc<LoadProgress>>) -> Result<Self>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+
+        let mut spreadsheet = Self::for_load(progress);
+        formats::load_fixed_width(path, &spreadsheet, &layout)?;
+
+        let mut dialect = Dialect::for_path(path).unwrap_or_default();
+        dialect.has_headers = layout.has_headers;
+        dialect.encoding = layout.encoding;
+        spreadsheet.dialect.set(dialect);
+        spreadsheet.text = true;
+        spreadsheet.set_path(path);
+
+        Ok(spreadsheet)
+    }
+
+    /// Open a file of JSON records using the given options.
+    pub fn open_json<P>(path: P, options: JsonOptions, progress: Option<&Arc<LoadProgress>>) -> Result<Self>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+
+        let mut spreadsheet = Self::for_load(progress);
+        formats::load_json(path, &spreadsheet, &options)?;
+        spreadsheet.set_path(path);
 
         Ok(spreadsheet)
     }
 
-    /// Save the spreadsheet to a file.
+    /// Save the spreadsheet to a file, in the format given by the file extension.
+    ///
+    /// When saving to a delimited text file of the same type the spreadsheet was opened from, the original dialect is
+    /// preserved. Otherwise the conventional dialect for the file extension is used. Either way the file is written in
+    /// the spreadsheet's current encoding. Text files are compressed if the path ends with a compressed file extension.
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        formats::save_csv(path.as_ref(), self)?;
+        let path = path.as_ref();
+        self.check_not_source(path)?;
+
+        let saver = match formats::uncompressed_extension(path).as_ref().map(String::as_str) {
+            Some("arrow") | Some("feather") | Some("ipc") => formats::save_ipc,
+            Some("db") | Some("sqlite") | Some("sqlite3") => {
+                return self.save_sqlite_table(path, &self.table_name(path));
+            }
+            Some("json") => return self.save_json(path, JsonOptions::default()),
+            Some("jsonl") | Some("ndjson") => {
+                return self.save_json(path, JsonOptions {
+                    layout: JsonLayout::Lines,
+                    ..JsonOptions::default()
+                });
+            }
+            Some("ods") => formats::save_ods,
+            Some("parquet") => return self.save_parquet(path, ParquetOptions::default()),
+            Some("sql") => return self.save_sql(path, &self.table_name(path), SqlOptions::default()),
+            Some("xls") | Some("xlsm") => {
+                return Err("This workbook format can only be read. Save the spreadsheet as .xlsx instead.".into());
+            }
+            Some("xlsx") => formats::save_xlsx,
+            Some("zip") => return Err("Spreadsheets cannot be saved into ZIP archives.".into()),
+            _ if Dialect::for_path(path).is_some() || self.is_same_type(path) => {
+                return self.save_delimited(path, self.save_dialect(path));
+            }
+            _ => return Err("Unknown file extension.".into()),
+        };
+
+        check_uncompressed(path)?;
+        saver(path, self)?;
         self.clear_dirty();
 
         Ok(())
     }
 
+    /// Save the spreadsheet to a delimited text file using the given dialect.
+    pub fn save_delimited<P: AsRef<Path>>(&self, path: P, dialect: Dialect) -> Result<()> {
+        self.check_not_source(path.as_ref())?;
+        formats::save_delimited(path.as_ref(), self, &dialect)?;
+        self.clear_dirty();
+
+        Ok(())
+    }
+
+    /// Save the spreadsheet to a file of JSON records using the given options.
+    pub fn save_json<P: AsRef<Path>>(&self, path: P, options: JsonOptions) -> Result<()> {
+        let path = path.as_ref();
+        self.check_not_source(path)?;
+
+        match options.layout {
+            JsonLayout::Array => formats::save_json(path, self, &options)?,
+            JsonLayout::Lines => formats::save_ndjson(path, self, &options)?,
+        }
+        self.clear_dirty();
+
+        Ok(())
+    }
+
+    /// Save the spreadsheet to a Parquet file using the given options.
+    pub fn save_parquet<P: AsRef<Path>>(&self, path: P, options: ParquetOptions) -> Result<()> {
+        check_uncompressed(path.as_ref())?;
+        self.check_not_source(path.as_ref())?;
+        formats::save_parquet(path.as_ref(), self, &options)?;
+        self.clear_dirty();
+
+        Ok(())
+    }
+
+    /// Save the spreadsheet as a SQL script that creates a table with the given name and inserts every row into it.
+    pub fn save_sql<P: AsRef<Path>>(&self, path: P, table: &str, options: SqlOptions) -> Result<()> {
+        self.check_not_source(path.as_ref())?;
+        formats::save_sql(path.as_ref(), self, table, &options)?;
+        self.clear_dirty();
+
+        Ok(())
+    }
+
+    /// Save the spreadsheet to a table of a SQLite database file.
+    ///
+    /// If the spreadsheet was opened from the same table, the changes made to it are written back to the table.
+    /// Otherwise its rows are appended to the table, which is created if it does not exist yet.
+    pub fn save_sqlite_table<P: AsRef<Path>>(&self, path: P, table: &str) -> Result<()> {
+        let path = path.as_ref();
+        check_uncompressed(path)?;
+        self.check_not_source(path)?;
+
+        // The same database may be given by another path, such as a relative path or a link.
+        let same_file = match self.path {
+            Some(ref original) => path.exists() && fs::canonicalize(path).ok() == fs::canonicalize(original).ok(),
+            None => false,
+        };
+
+        if same_file && self.sheet() == Some(table) {
+            formats::save_sqlite_table(path, table, self)?;
+        } else {
+            formats::append_sqlite_table(path, table, self)?;
+        }
+        self.clear_dirty();
+
+        Ok(())
+    }
+
+    /// Check if the rows of the spreadsheet are read from its file in place, instead of being imported.
+    pub fn is_indexed(&self) -> bool {
+        self.storage.source().is_some()
+    }
+
+    /// Get the progress of indexing the file the spreadsheet is viewed in place from, if it is still being indexed.
+    pub fn indexing(&self) -> Option<&Arc<LoadProgress>> {
+        if self.is_indexed() {
+            self.storage.progress()
+        } else {
+            None
+        }
+    }
+
+    /// Get the progress of the load filling the spreadsheet, if it was loaded on a background thread.
+    pub fn load_progress(&self) -> Option<&Arc<LoadProgress>> {
+        self.loading.as_ref()
+    }
+
     /// Get the name of the spreadsheet.
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    /// Get the path of the file the spreadsheet was opened from.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Get the name of the worksheet the spreadsheet was opened from.
+    pub fn sheet(&self) -> Option<&str> {
+        self.sheet.as_ref().map(String::as_str)
+    }
+
+    /// Get the dialect used for reading and writing delimited text.
+    pub fn dialect(&self) -> Dialect {
+        self.dialect.get()
+    }
+
+    /// Set the dialect used for writing delimited text.
+    pub fn set_dialect(&self, dialect: Dialect) {
+        self.dialect.set(dialect);
+    }
+
+    /// Get the problems found in the file while loading the spreadsheet.
+    pub fn load_report(&self) -> LoadReport {
+        self.report.borrow().clone()
+    }
+
+    /// Record the problems found in the file while loading the spreadsheet.
+    pub fn set_load_report(&self, report: LoadReport) {
+        *self.report.borrow_mut() = report;
+    }
+
+    /// Get the character encoding the spreadsheet was read from, and will be saved in.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.dialect().encoding
+    }
+
+    /// Set the character encoding to save the spreadsheet in, and whether to start the file with a byte order mark.
+    pub fn set_encoding(&self, encoding: &'static Encoding, bom: bool) {
+        let mut dialect = self.dialect();
+        dialect.encoding = encoding;
+        dialect.bom = bom;
+        self.dialect.set(dialect);
+    }
+
     /// Check if the spreadsheet has been modified.
     pub fn is_dirty(&self) -> bool {
         self.dirty.get()
@@ -100,24 +461,68 @@ impl Spreadsheet {
         self.dirty.set(false);
     }
 
+    /// Get the name of the database table to save the spreadsheet as: the worksheet or table it was opened from, or
+    /// otherwise the name of its file.
+    pub fn table_name(&self, path: &Path) -> String {
+        self.sheet.clone()
+            .or_else(|| self.path.as_ref().and_then(|path| file_stem(path)))
+            .or_else(|| file_stem(path))
+            .unwrap_or_else(|| String::from("data"))
+    }
+
+    /// Get the dialect to save a delimited text file with.
+    fn save_dialect(&self, path: &Path) -> Dialect {
+        let mut dialect = if self.is_same_type(path) {
+            self.dialect()
+        } else {
+            Dialect::for_path(path).unwrap_or(self.dialect())
+        };
+        dialect.encoding = self.dialect().encoding;
+        dialect.bom = self.dialect().bom;
+
+        dialect
+    }
+
+    /// Check if a path has the same extension as the delimited or fixed-width text file the spreadsheet was loaded
+    /// from.
+    fn is_same_type(&self, path: &Path) -> bool {
+        if !self.text {
+            return false;
+        }
+
+        match self.path {
+            Some(ref original) => formats::uncompressed_extension(original) == formats::uncompressed_extension(path),
+            None => false,
+        }
+    }
+
+    /// Record the file the spreadsheet was loaded from and mark it as unmodified.
+    fn set_path(&mut self, path: &Path) {
+        if let Some(file_name) = path.file_name() {
+            self.name = format!("{}", file_name.to_string_lossy());
+        }
+        self.path = Some(path.to_path_buf());
+        self.clear_dirty();
+    }
+
     /// Get the number of columns in the spreadsheet.
     pub fn get_column_count(&self) -> i64 {
-        self.database.query_row("SELECT COUNT(id) FROM columns", &[], |row| {
-            row.get(0)
-        }).unwrap()
+        self.storage.get_column_count()
     }
 
     /// Get the spreadsheet columns.
     pub fn get_columns(&self) -> Vec<String> {
-        let mut stmt = self.database.prepare_cached("SELECT name FROM columns ORDER BY id ASC").unwrap();
-        let mut rows = stmt.query(&[]).unwrap();
-        let mut columns = Vec::new();
+        self.storage.get_columns()
+    }
 
-        while let Some(Ok(row)) = rows.next() {
-            columns.push(row.get(0));
-        }
+    /// Get the data type of each column, for columns loaded from a file that records the type of its values.
+    pub fn get_column_types(&self) -> Vec<Option<String>> {
+        self.storage.get_column_types()
+    }
 
-        columns
+    /// Set the data type of a column, so that it can be preserved when saving to a format that records types.
+    pub fn set_column_type(&self, column: i64, data_type: Option<&str>) -> Result<()> {
+        self.storage.set_column_type(column, data_type)
     }
 
     /// Inserts columns starting at the given position.
@@ -128,122 +533,124 @@ impl Spreadsheet {
             InsertPosition::End => self.get_column_count(),
         };
 
-        // Shift columns to the right to make room for the given column count.
-        let shift_amount = names.len() as i64;
-        self.database.execute("
-            UPDATE columns
-            SET id = id + ?
-            WHERE id >= ?
-        ", &[
-            &shift_amount,
-            &position,
-        ])?;
-
-        // Insert the new columns.
-        let mut stmt = self.database.prepare_cached("INSERT INTO columns (id, name) VALUES (?, ?)")?;
-        for (offset, value) in names.into_iter().enumerate() {
-            let pos = position + offset as i64;
-            stmt.execute(&[&pos, &value])?;
-        }
+        self.storage.insert_columns(position, names)?;
+        self.dirty.set(true);
 
+        Ok(())
+    }
+
+    /// Rename a column.
+    pub fn rename_column(&self, column: i64, name: &str) -> Result<()> {
+        self.storage.rename_column(column, name)?;
         self.dirty.set(true);
 
         Ok(())
     }
 
+    /// Use the values of a row as the column names, and remove the row. Columns without a value in the row are named
+    /// using the given scheme instead.
+    pub fn promote_row_to_header(&self, row: i64, naming: ColumnNaming) -> Result<()> {
+        if row < 0 || row >= self.get_row_count() {
+            return Err("The row does not exist.".into());
+        }
+
+        let values = self.get_rows(row, row)?.pop().unwrap_or_default();
+        self.delete_rows(row, row)?;
+
+        for column in 0..self.get_column_count() {
+            let name = match values.get(column as usize) {
+                Some(&Some(ref value)) if !value.is_empty() => value.clone(),
+                _ => naming.name(column as usize),
+            };
+            self.rename_column(column, &name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Move the column names into a new first row, and name the columns using the given scheme instead.
+    pub fn demote_header_to_row(&self, naming: ColumnNaming) -> Result<()> {
+        let names = self.get_columns();
+        self.insert_row(InsertPosition::Index(0), names.clone())?;
+
+        for column in 0..names.len() {
+            self.rename_column(column as i64, &naming.name(column))?;
+        }
+
+        Ok(())
+    }
+
     /// Get the value of a specific cell.
     pub fn get_cell(&self, row: i64, column: i64) -> Option<String> {
-        self.database.query_row("
-            SELECT value FROM cells
-            WHERE row = ? AND column = ?
-        ", &[&row, &column], |row| {
-            row.get(0)
-        }).unwrap_or(None)
+        self.storage.get_cell(row, column)
     }
 
     /// Set the value of a specific cell.
     pub fn set_cell<S: Into<Option<String>>>(&self, row: i64, column: i64, value: S) -> Result<()> {
-        self.database.execute("
-            UPDATE cells
-            SET value = ?
-            WHERE row = ? AND column = ?
-        ", &[
-            &value.into(),
-            &row,
-            &column
-        ])?;
+        if column < 0 {
+            return Err("The column does not exist.".into());
+        }
 
+        self.storage.set_cell(row, column, value.into())?;
         self.dirty.set(true);
 
         Ok(())
     }
 
-    /// Get the number of rows in the spreadsheet.
+    /// Get the number of rows in the spreadsheet, or the number loaded or indexed so far if it is still being loaded.
     pub fn get_row_count(&self) -> i64 {
-        self.row_count.get()
+        self.storage.get_row_count()
     }
 
     /// Get a range of values.
+    ///
+    /// Every row has a value for each column, so rows with missing cells are padded with `None`.
     pub fn get_rows(&self, start: i64, end: i64) -> Result<Vec<Vec<Option<String>>>> {
-        let mut stmt = self.database.prepare_cached("
-            SELECT row, value FROM cells
-            WHERE row >= ? AND row <= ?
-            ORDER BY row, column ASC
-        ")?;
-
         info!("loading spreadsheet values in rows {} - {}", start, end);
-        let mut results = stmt.query(&[&start, &end])?;
-        let mut rows = Vec::new();
-        let mut row = Vec::new();
-
-        while let Some(result) = results.next() {
-            let result = result?;
-            let current_row: i64 = result.get(0);
-
-            if current_row - start > rows.len() as i64 {
-                rows.push(row);
-                row = Vec::new();
-            }
-
-            row.push(result.get(1));
-        }
-
-        rows.push(row);
+        let rows = self.storage.get_rows(start, end)?;
         info!("got back {} rows", rows.len());
 
         Ok(rows)
     }
 
     /// Insert a row into the spreadsheet beginning at the specified position.
-    pub fn insert_row(&self, position: InsertPosition, values: Vec<String>) -> Result<()> {
+    ///
+    /// To add many rows to the end of the spreadsheet, such as when loading a file, use `bulk_insert` instead.
+    pub fn insert_row<S: Into<Option<String>>>(&self, position: InsertPosition, values: Vec<S>) -> Result<()> {
         // Get the absolute index to insert at.
         let row = match position {
             InsertPosition::Index(i) => i,
             InsertPosition::End => self.get_row_count(),
         };
 
-        // Shift rows below down by one.
-        if position != InsertPosition::End {
-            self.database.execute("
-                UPDATE cells
-                SET row = row + 1
-                WHERE row >= ?
-            ", &[&row])?;
-        }
-
-        // Insert the cells into the new row.
-        let mut cell_stmt = self.database.prepare_cached("INSERT INTO cells (column, row, value) VALUES (?, ?, ?)")?;
-        for (pos, value) in values.into_iter().enumerate() {
-            let column = pos as i64;
-            cell_stmt.execute(&[&column, &row, &value])?;
-        }
-
-        self.row_count.set(self.row_count.get() + 1);
+        self.storage.insert_row(row, values.into_iter().map(Into::into).collect())?;
         self.dirty.set(true);
 
         Ok(())
     }
 
+    /// Start appending rows to the end of the spreadsheet in bulk, for loading a file.
+    ///
+    /// Until the returned inserter is finished, the storage may be tuned for writing and hold back some of the rows, so
+    /// the spreadsheet should not be edited in the meantime, other than to add columns.
+    pub fn bulk_insert(&self) -> Result<BulkInsert> {
+        Ok(BulkInsert {
+            spreadsheet: self,
+            writer: self.storage.bulk_insert()?,
+        })
+    }
+
+    /// Get the keys identifying a range of rows in the file they were loaded from. Rows that were added since have no
+    /// key.
+    pub fn get_row_keys(&self, start: i64, end: i64) -> Result<Vec<Option<String>>> {
+        self.storage.get_row_keys(start, end)
+    }
+
+    /// Set the key identifying a row in the file it was loaded from, so that changes to the row can be written back.
+    pub fn set_row_key(&self, row: i64, key: Option<&str>) -> Result<()> {
+        self.storage.set_row_key(row, key)
+    }
+
     /// Delete a range of rows.
     pub fn delete_rows(&self, start: i64, end: i64) -> Result<()> {
         let start = max(0, min(self.get_row_count(), start));
@@ -253,31 +660,76 @@ impl Spreadsheet {
             return Err("Starting row must be greater than or equal to the ending row".into());
         }
 
-        let count = end - start + 1;
-        info!("deleting {} rows ({} - {})", count, start, end);
-
-        // Delete cells belonging to the rows.
-        self.database.execute("
-            DELETE FROM cells
-            WHERE row >= ? AND row <= ?
-        ", &[
-            &start,
-            &end,
-        ])?;
-
-        // Shift rows after the deleted range back up.
-        self.database.execute("
-            UPDATE cells
-            SET row = row - ?
-            WHERE row > ?
-        ", &[
-            &count,
-            &end,
-        ])?;
-
-        self.row_count.set(self.get_row_count() - count);
+        info!("deleting {} rows ({} - {})", end - start + 1, start, end);
+        self.storage.delete_rows(start, end)?;
         self.dirty.set(true);
 
         Ok(())
     }
+
+    /// Fail if a path is the file the spreadsheet is viewed in place from, since it cannot be overwritten while it is
+    /// being read.
+    fn check_not_source(&self, path: &Path) -> Result<()> {
+        if let Some(source) = self.storage.source() {
+            if path.exists() && fs::canonicalize(path).ok() == fs::canonicalize(source).ok() {
+                return Err("The file is being viewed in place and cannot be overwritten.".into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Appends rows to the end of a spreadsheet quickly, for loading files. Created by `Spreadsheet::bulk_insert`.
+///
+/// Rows become part of the spreadsheet as the storage commits them, and the last of them once the inserter is
+/// finished. Dropping the inserter without finishing it may discard some of the rows.
+pub struct BulkInsert<'a> {
+    spreadsheet: &'a Spreadsheet,
+    writer: Box<BulkWriter + 'a>,
+}
+
+impl<'a> BulkInsert<'a> {
+    /// Append a row to the spreadsheet.
+    pub fn push<S: Into<Option<String>>>(&mut self, values: Vec<S>) -> Result<()> {
+        self.add(values, None)
+    }
+
+    /// Append a row to the spreadsheet, along with the key identifying it in the file it was loaded from.
+    pub fn push_with_key<S: Into<Option<String>>>(&mut self, values: Vec<S>, key: &str) -> Result<()> {
+        self.add(values, Some(key.to_string()))
+    }
+
+    /// Insert the remaining rows, and return the storage to normal use.
+    pub fn finish(self) -> Result<()> {
+        self.writer.finish()
+    }
+
+    fn add<S: Into<Option<String>>>(&mut self, values: Vec<S>, key: Option<String>) -> Result<()> {
+        // Stop a load running in the background as soon as it is cancelled.
+        if let Some(ref progress) = self.spreadsheet.loading {
+            progress.check_cancelled()?;
+        }
+
+        self.writer.push(values.into_iter().map(Into::into).collect(), key)?;
+        self.spreadsheet.dirty.set(true);
+
+        Ok(())
+    }
+}
+
+
+/// Get the name of a file without its extension, ignoring any compressed file extension after it.
+fn file_stem(path: &Path) -> Option<String> {
+    formats::uncompressed_path(path).file_stem().map(|s| s.to_string_lossy().into_owned())
+}
+
+/// Fail if a path has a compressed file extension. Only formats that are read and written as a stream of text can be
+/// compressed as a whole.
+fn check_uncompressed(path: &Path) -> Result<()> {
+    match formats::Compression::for_path(path) {
+        Some(compression) => Err(format!("This file format cannot be {} compressed.", compression).into()),
+        None => Ok(()),
+    }
 }
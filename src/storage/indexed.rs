@@ -0,0 +1,170 @@
+//! Storing spreadsheets as delimited text files read in place, with edited rows held in memory.
+use formats::{Dialect, IndexedFile};
+use progress::LoadProgress;
+use Result;
+use std::cell::RefCell;
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use super::{BulkWriter, Storage};
+
+
+/// Storage that reads rows from a delimited text file in place, without importing it. Rows can be read while the file
+/// is still being indexed in the background.
+///
+/// Only the rows that have been edited are held in memory, in place of the rows of the file. Since every row is a
+/// record of the file, rows cannot be added or removed, and columns can only be added to the end.
+pub struct IndexedStorage {
+    source: IndexedFile,
+    columns: RefCell<Vec<String>>,
+    column_types: RefCell<Vec<Option<String>>>,
+
+    /// Cells of the rows that have been edited, by row number.
+    edits: RefCell<BTreeMap<i64, Vec<Option<String>>>>,
+}
+
+impl IndexedStorage {
+    /// Open a delimited text file, and start indexing it in the background.
+    pub fn open(path: &Path, dialect: &Dialect) -> Result<Self> {
+        let source = IndexedFile::open(path, dialect)?;
+        let columns = source.columns().to_vec();
+
+        Ok(Self {
+            column_types: RefCell::new(vec![None; columns.len()]),
+            columns: RefCell::new(columns),
+            source: source,
+            edits: RefCell::new(BTreeMap::new()),
+        })
+    }
+
+    /// Get the cells of a row as they are now, or `None` if the row does not exist.
+    fn get_row_cells(&self, row: i64) -> Result<Option<Vec<Option<String>>>> {
+        match self.edits.borrow().get(&row) {
+            Some(cells) => Ok(Some(cells.clone())),
+            None => Ok(self.source.get_rows(row, 1)?.pop()),
+        }
+    }
+
+    /// Fail because rows cannot be added or removed.
+    fn rows_fixed<T>(&self) -> Result<T> {
+        Err("Rows cannot be added or removed in a file viewed without importing it.".into())
+    }
+}
+
+impl Storage for IndexedStorage {
+    fn get_columns(&self) -> Vec<String> {
+        self.columns.borrow().clone()
+    }
+
+    fn get_column_types(&self) -> Vec<Option<String>> {
+        self.column_types.borrow().clone()
+    }
+
+    fn set_column_type(&self, column: i64, data_type: Option<&str>) -> Result<()> {
+        if let Some(column_type) = self.column_types.borrow_mut().get_mut(column as usize) {
+            *column_type = data_type.map(str::to_string);
+        }
+
+        Ok(())
+    }
+
+    fn insert_columns(&self, position: i64, names: Vec<String>) -> Result<()> {
+        if position < self.get_column_count() {
+            return Err("Columns can only be added to the end of a file viewed without importing it.".into());
+        }
+
+        self.column_types.borrow_mut().extend(names.iter().map(|_| None));
+        self.columns.borrow_mut().extend(names);
+
+        Ok(())
+    }
+
+    fn rename_column(&self, column: i64, name: &str) -> Result<()> {
+        if let Some(column) = self.columns.borrow_mut().get_mut(column as usize) {
+            *column = name.to_string();
+        }
+
+        Ok(())
+    }
+
+    fn get_row_count(&self) -> i64 {
+        self.source.row_count()
+    }
+
+    fn get_rows(&self, start: i64, end: i64) -> Result<Vec<Vec<Option<String>>>> {
+        let end = min(end, self.get_row_count() - 1);
+        let column_count = self.get_column_count() as usize;
+        let mut rows = vec![vec![None; column_count]; max(0, end - start + 1) as usize];
+
+        if rows.is_empty() {
+            return Ok(rows);
+        }
+
+        for (row, cells) in rows.iter_mut().zip(self.source.get_rows(start, end - start + 1)?) {
+            fill_row(row, cells);
+        }
+
+        for (&row, cells) in self.edits.borrow().range(start..end + 1) {
+            fill_row(&mut rows[(row - start) as usize], cells.clone());
+        }
+
+        Ok(rows)
+    }
+
+    fn get_cell(&self, row: i64, column: i64) -> Option<String> {
+        match self.get_row_cells(row) {
+            Ok(Some(mut cells)) if column >= 0 && (column as usize) < cells.len() => cells.swap_remove(column as usize),
+            _ => None,
+        }
+    }
+
+    fn set_cell(&self, row: i64, column: i64, value: Option<String>) -> Result<()> {
+        if let Some(mut cells) = self.get_row_cells(row)? {
+            if column as usize >= cells.len() {
+                cells.resize(column as usize + 1, None);
+            }
+            cells[column as usize] = value;
+
+            self.edits.borrow_mut().insert(row, cells);
+        }
+
+        Ok(())
+    }
+
+    fn insert_row(&self, _row: i64, _values: Vec<Option<String>>) -> Result<()> {
+        self.rows_fixed()
+    }
+
+    fn bulk_insert<'a>(&'a self) -> Result<Box<BulkWriter + 'a>> {
+        self.rows_fixed()
+    }
+
+    fn delete_rows(&self, _start: i64, _end: i64) -> Result<()> {
+        self.rows_fixed()
+    }
+
+    fn source(&self) -> Option<&Path> {
+        Some(self.source.path())
+    }
+
+    fn progress(&self) -> Option<&Arc<LoadProgress>> {
+        if self.source.is_complete() {
+            None
+        } else {
+            Some(self.source.progress())
+        }
+    }
+}
+
+
+/// Copy the cells of a row into a row padded to the number of columns, extending it if the row has more cells.
+fn fill_row(row: &mut Vec<Option<String>>, cells: Vec<Option<String>>) {
+    if cells.len() > row.len() {
+        row.resize(cells.len(), None);
+    }
+
+    for (column, value) in cells.into_iter().enumerate() {
+        row[column] = value;
+    }
+}
@@ -0,0 +1,94 @@
+//! Backends storing the columns and rows of spreadsheets.
+mod indexed;
+mod sqlite;
+
+pub use self::indexed::*;
+pub use self::sqlite::*;
+
+use progress::LoadProgress;
+use Result;
+use std::cmp::max;
+use std::path::Path;
+use std::sync::Arc;
+
+
+/// Storage for the columns and rows of a spreadsheet.
+///
+/// Columns and rows are numbered from zero. A row may have fewer cells than there are columns, in which case the rest
+/// of its cells are empty, but rows returned by `get_rows` are always padded to the number of columns. Storage is
+/// changed through a shared reference, so that a spreadsheet can be edited while it is being read from.
+pub trait Storage: Send {
+    /// Get the number of columns.
+    fn get_column_count(&self) -> i64 {
+        self.get_columns().len() as i64
+    }
+
+    /// Get the names of the columns.
+    fn get_columns(&self) -> Vec<String>;
+
+    /// Get the data type of each column, for columns loaded from a file that records the type of its values.
+    fn get_column_types(&self) -> Vec<Option<String>>;
+
+    /// Set the data type of a column.
+    fn set_column_type(&self, column: i64, data_type: Option<&str>) -> Result<()>;
+
+    /// Insert columns at an index, moving the cells of the columns after it to the right.
+    fn insert_columns(&self, position: i64, names: Vec<String>) -> Result<()>;
+
+    /// Rename a column.
+    fn rename_column(&self, column: i64, name: &str) -> Result<()>;
+
+    /// Get the number of rows, or the number available so far if rows are still being added by another thread.
+    fn get_row_count(&self) -> i64;
+
+    /// Get a range of rows, padded to the number of columns.
+    fn get_rows(&self, start: i64, end: i64) -> Result<Vec<Vec<Option<String>>>>;
+
+    /// Get the value of a cell, or `None` if it is empty or does not exist.
+    fn get_cell(&self, row: i64, column: i64) -> Option<String>;
+
+    /// Set the value of a cell. Nothing is changed if the row does not exist.
+    fn set_cell(&self, row: i64, column: i64, value: Option<String>) -> Result<()>;
+
+    /// Insert a row at an index, moving the rows after it down.
+    fn insert_row(&self, row: i64, values: Vec<Option<String>>) -> Result<()>;
+
+    /// Start appending rows to the end in bulk, for loading a file.
+    fn bulk_insert<'a>(&'a self) -> Result<Box<BulkWriter + 'a>>;
+
+    /// Delete a range of rows, moving the rows after it up.
+    fn delete_rows(&self, start: i64, end: i64) -> Result<()>;
+
+    /// Get the keys identifying a range of rows in the file they were loaded from. Storage that does not record keys
+    /// has none.
+    fn get_row_keys(&self, start: i64, end: i64) -> Result<Vec<Option<String>>> {
+        Ok(vec![None; max(0, end - start + 1) as usize])
+    }
+
+    /// Set the key identifying a row in the file it was loaded from.
+    fn set_row_key(&self, _row: i64, _key: Option<&str>) -> Result<()> {
+        Err("Row keys cannot be recorded for this spreadsheet.".into())
+    }
+
+    /// Get the file the rows are read from in place, if any. The file must not be overwritten while it is in use.
+    fn source(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Get the progress of rows still being added by another thread, if any.
+    fn progress(&self) -> Option<&Arc<LoadProgress>> {
+        None
+    }
+}
+
+
+/// Appends rows to the end of a storage in bulk, for loading files. Created by `Storage::bulk_insert`.
+///
+/// Dropping the writer without finishing it may discard some of the rows pushed to it.
+pub trait BulkWriter {
+    /// Append a row, along with the key identifying it in the file it was loaded from, if any.
+    fn push(&mut self, values: Vec<Option<String>>, key: Option<String>) -> Result<()>;
+
+    /// Append the remaining rows, and return the storage to normal use.
+    fn finish(self: Box<Self>) -> Result<()>;
+}
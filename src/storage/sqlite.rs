@@ -0,0 +1,852 @@
+//! Storing spreadsheets in a scratch SQLite database, one record per row.
+use byteorder::{ByteOrder, LittleEndian};
+use progress::LoadProgress;
+use Result;
+use rusqlite::Connection;
+use rusqlite::types::ToSql;
+use std::cell::{Cell, RefCell};
+use std::cmp::{max, min};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use super::{BulkWriter, Storage};
+
+
+/// Number of scratch database files created by this process, used to give each one a unique name.
+static SCRATCH_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Maximum number of rows inserted by one statement while loading, keeping within SQLite's limit of 999 parameters.
+const ROWS_PER_STATEMENT: usize = 333;
+
+/// Number of cells loaded in each transaction. Rows loaded in the background can be browsed once their transaction is
+/// committed.
+const CELLS_PER_TRANSACTION: usize = 100_000;
+
+/// Size of the database page cache while loading a file, in KiB.
+const LOAD_CACHE_SIZE: i64 = 256 * 1024;
+
+/// Number of rows rewritten at a time when changing the cells of every row.
+const REWRITE_PAGE_SIZE: i64 = 1000;
+
+/// Number of IDs reserved for each block of rows, and so the greatest number of rows a block can hold.
+const BLOCK_SIZE: i64 = 4096;
+
+
+/// Storage in a scratch SQLite database, which keeps spreadsheets of any size out of memory.
+///
+/// Rows are kept in blocks of consecutive rows, each of which reserves a range of IDs for its rows to be stored under
+/// in order. Only the size and order of the blocks are held in memory, so that a row is found by its number without
+/// reading the database, and adding or removing a row only renumbers the rows of its own block.
+pub struct SqliteStorage {
+    /// Open SQLite database for storing spreadsheet data.
+    database: Connection,
+
+    /// Named file holding the database, if it must be opened again while the spreadsheet is loaded in the background.
+    /// Declared after the database so that it is removed after the database is closed. It is only held to be dropped.
+    _scratch: Option<ScratchFile>,
+
+    /// Progress of the load filling the database, if this is a view of a spreadsheet still being loaded.
+    loading: Option<Arc<LoadProgress>>,
+
+    /// Progress of the load filling the database from this storage, if it runs on a background thread. Rows count
+    /// towards it as they are committed.
+    filling: Option<Arc<LoadProgress>>,
+
+    /// Number of rows in the database.
+    row_count: Cell<i64>,
+
+    /// Blocks of rows, in row order.
+    blocks: RefCell<Vec<Block>>,
+
+    /// Number of blocks that have reserved IDs so far, including any that were removed since.
+    reserved_blocks: Cell<i64>,
+}
+
+/// A run of consecutive rows, stored under consecutive IDs from the start of the range of IDs reserved for it.
+#[derive(Clone, Copy)]
+struct Block {
+    /// Number of the first row in the block.
+    start: i64,
+
+    /// First ID reserved for the block.
+    first_id: i64,
+
+    /// Number of rows in the block.
+    count: i64,
+}
+
+/// Scratch database file that is removed when the storage using it is dropped.
+struct ScratchFile {
+    path: PathBuf,
+}
+
+impl ScratchFile {
+    /// Choose a unique path for a scratch database in the temporary directory.
+    fn new() -> Self {
+        let count = SCRATCH_FILES.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            path: env::temp_dir().join(format!("astinus-{}-{}.db", process::id(), count)),
+        }
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+
+        // Views of the spreadsheet that are still open keep the write-ahead log files from being removed by SQLite.
+        for suffix in &["-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl SqliteStorage {
+    /// Create an empty database.
+    pub fn new() -> Self {
+        Self::create(None)
+    }
+
+    /// Create an empty database to load a file into on a background thread, in a named file that can be opened with
+    /// `open_partial` while the load runs.
+    pub fn for_load(progress: &Arc<LoadProgress>) -> Self {
+        Self::create(Some(progress))
+    }
+
+    /// Create an empty database, for a load running in the background if its progress is given.
+    fn create(progress: Option<&Arc<LoadProgress>>) -> Self {
+        // Open an on-disk, temporary scratch database. A spreadsheet loaded in the background uses a named file
+        // instead, so that it can be browsed while it is loading.
+        let scratch = progress.map(|_| ScratchFile::new());
+        let connection = Connection::open(scratch.as_ref().map_or(Path::new(""), |file| &file.path)).unwrap();
+
+        // Let the database be read while rows are being written.
+        if scratch.is_some() {
+            connection.execute_batch("PRAGMA journal_mode = WAL").unwrap();
+        }
+
+        // Durability does not matter for scratch data.
+        connection.execute_batch("PRAGMA synchronous = OFF").unwrap();
+
+        // Set up the schema.
+        connection.execute_batch("
+            CREATE TABLE columns (
+                id          INTEGER PRIMARY KEY NOT NULL,
+                name        TEXT NOT NULL,
+                data_type   TEXT
+            );
+
+            CREATE TABLE rows (
+                id          INTEGER PRIMARY KEY NOT NULL,
+                key         TEXT,
+                cells       BLOB NOT NULL
+            );
+        ").unwrap();
+
+        if let (Some(progress), Some(file)) = (progress, scratch.as_ref()) {
+            progress.set_database(&file.path);
+        }
+
+        Self {
+            database: connection,
+            _scratch: scratch,
+            loading: None,
+            filling: progress.cloned(),
+            row_count: Cell::new(0),
+            blocks: RefCell::new(Vec::new()),
+            reserved_blocks: Cell::new(0),
+        }
+    }
+
+    /// Open the database of a spreadsheet that is still being loaded on another thread, to read the rows loaded so far.
+    ///
+    /// Returns `None` if the load has not created the database yet. The database must not be changed.
+    pub fn open_partial(progress: &Arc<LoadProgress>) -> Result<Option<Self>> {
+        let database = match progress.database() {
+            Some(database) => database,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Self {
+            database: Connection::open(database)?,
+            _scratch: None,
+            loading: Some(progress.clone()),
+            filling: None,
+            row_count: Cell::new(0),
+            blocks: RefCell::new(Vec::new()),
+            reserved_blocks: Cell::new(0),
+        }))
+    }
+
+    /// Get the ranges of IDs that a range of rows are stored under, one for each block the rows are in, as the number
+    /// of the first row in the range and its first and last IDs. Rows that do not exist are left out.
+    fn id_ranges(&self, start: i64, end: i64) -> Vec<(i64, i64, i64)> {
+        let start = max(0, start);
+        let end = min(end, self.get_row_count() - 1);
+
+        if start > end {
+            return Vec::new();
+        }
+
+        // Rows loaded in order into an empty database are stored under their row numbers, since every block is filled
+        // before the next one is started.
+        if self.loading.is_some() {
+            return vec![(start, start, end)];
+        }
+
+        let blocks = self.blocks.borrow();
+        let mut ranges = Vec::new();
+
+        for block in &blocks[find_block(&blocks, start)..] {
+            if block.start > end {
+                break;
+            }
+
+            let first = max(start, block.start);
+            let last = min(end, block.start + block.count - 1);
+            ranges.push((first, block.first_id + first - block.start, block.first_id + last - block.start));
+        }
+
+        ranges
+    }
+
+    /// Get the ID a row is stored under, or `None` if the row does not exist.
+    fn row_id(&self, row: i64) -> Option<i64> {
+        self.id_ranges(row, row).first().map(|&(_, id, _)| id)
+    }
+
+    /// Get the cells of the row stored under an ID, or `None` if there is no such row.
+    fn get_row_cells(&self, id: i64) -> Result<Option<Vec<Option<String>>>> {
+        let mut stmt = self.database.prepare_cached("SELECT cells FROM rows WHERE id = ?")?;
+        let mut results = stmt.query(&[&id])?;
+
+        match results.next() {
+            Some(result) => {
+                let blob: Vec<u8> = result?.get(0);
+                Ok(Some(decode_cells(&blob)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Replace the cells of the row stored under an ID.
+    fn set_row_cells(&self, id: i64, cells: &[Option<String>]) -> Result<()> {
+        self.database.execute("UPDATE rows SET cells = ? WHERE id = ?", &[&encode_cells(cells.iter().cloned()), &id])?;
+
+        Ok(())
+    }
+
+    /// Change the cells of every row in a single transaction.
+    fn rewrite_rows<F: FnMut(&mut Vec<Option<String>>)>(&self, f: F) -> Result<()> {
+        self.database.execute_batch("BEGIN")?;
+
+        let result = self.rewrite_pages(f);
+        self.database.execute_batch(if result.is_ok() { "COMMIT" } else { "ROLLBACK" })?;
+
+        result
+    }
+
+    /// Change the cells of every row, a page of rows at a time in the order of their IDs. Only rows that are changed
+    /// are written back.
+    fn rewrite_pages<F: FnMut(&mut Vec<Option<String>>)>(&self, mut f: F) -> Result<()> {
+        let mut stmt = self.database.prepare_cached("
+            SELECT id, cells FROM rows
+            WHERE id >= ?
+            ORDER BY id ASC
+            LIMIT ?
+        ")?;
+
+        let mut next_id = 0;
+        loop {
+            let mut changed = Vec::new();
+            let mut count = 0;
+
+            {
+                let mut results = stmt.query(&[&next_id, &REWRITE_PAGE_SIZE])?;
+
+                while let Some(result) = results.next() {
+                    let result = result?;
+                    let id: i64 = result.get(0);
+                    let blob: Vec<u8> = result.get(1);
+                    let mut cells = decode_cells(&blob)?;
+                    let original = cells.clone();
+
+                    f(&mut cells);
+                    if cells != original {
+                        changed.push((id, cells));
+                    }
+
+                    next_id = id + 1;
+                    count += 1;
+                }
+            }
+
+            for (id, cells) in changed {
+                self.set_row_cells(id, &cells)?;
+            }
+
+            if count < REWRITE_PAGE_SIZE {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reserve the IDs of a new block, returning the first of them.
+    fn reserve_block(&self) -> i64 {
+        let block = self.reserved_blocks.get();
+        self.reserved_blocks.set(block + 1);
+
+        block * BLOCK_SIZE
+    }
+
+    /// Make room for a row after the last row, returning the ID to store it under. A new block is started once the
+    /// last one is full.
+    fn append_row(&self) -> i64 {
+        let mut blocks = self.blocks.borrow_mut();
+        let row = blocks.last().map_or(0, |block| block.start + block.count);
+
+        if let Some(block) = blocks.last_mut() {
+            if block.count < BLOCK_SIZE {
+                block.count += 1;
+                return block.first_id + block.count - 1;
+            }
+        }
+
+        let first_id = self.reserve_block();
+        blocks.push(Block {
+            start: row,
+            first_id: first_id,
+            count: 1,
+        });
+
+        first_id
+    }
+
+    /// Make room for a row before an existing row, moving the rows after it in its block down. Returns the ID to store
+    /// the new row under.
+    fn make_room(&self, row: i64) -> Result<i64> {
+        let mut index = find_block(&self.blocks.borrow(), row);
+
+        if self.blocks.borrow()[index].count == BLOCK_SIZE {
+            self.split_block(index)?;
+
+            if row >= self.blocks.borrow()[index + 1].start {
+                index += 1;
+            }
+        }
+
+        let block = self.blocks.borrow()[index];
+        let id = block.first_id + row - block.start;
+        self.shift_rows(id, block.first_id + block.count, 1)?;
+
+        let mut blocks = self.blocks.borrow_mut();
+        blocks[index].count += 1;
+        renumber_blocks(&mut blocks);
+
+        Ok(id)
+    }
+
+    /// Split a full block in two, moving the second half of its rows to a new block after it.
+    fn split_block(&self, index: usize) -> Result<()> {
+        let block = self.blocks.borrow()[index];
+        let half = block.count / 2;
+        let first_id = self.reserve_block();
+
+        // The new block's IDs are unused, so the rows can be moved there directly.
+        self.database.execute("
+            UPDATE rows
+            SET id = id + ?
+            WHERE id >= ? AND id < ?
+        ", &[
+            &(first_id - block.first_id - half),
+            &(block.first_id + half),
+            &(block.first_id + block.count),
+        ])?;
+
+        let mut blocks = self.blocks.borrow_mut();
+        blocks[index].count = half;
+        blocks.insert(index + 1, Block {
+            start: block.start + half,
+            first_id: first_id,
+            count: block.count - half,
+        });
+
+        Ok(())
+    }
+
+    /// Move the rows stored under a range of IDs within a block by an amount, to make room for a row or close the gap
+    /// left by deleted ones.
+    ///
+    /// IDs are unique, so the rows are moved out of the way to negative IDs first, and then to where they belong.
+    fn shift_rows(&self, start_id: i64, end_id: i64, amount: i64) -> Result<()> {
+        if start_id >= end_id {
+            return Ok(());
+        }
+
+        self.database.execute("
+            UPDATE rows
+            SET id = -(id + ?) - 1
+            WHERE id >= ? AND id < ?
+        ", &[
+            &amount,
+            &start_id,
+            &end_id,
+        ])?;
+
+        self.database.execute("
+            UPDATE rows
+            SET id = -id - 1
+            WHERE id < 0
+        ", &[])?;
+
+        Ok(())
+    }
+
+    /// Forget the blocks of rows past a number of rows, after the rows were discarded from the database.
+    fn truncate_blocks(&self, row_count: i64) {
+        let mut blocks = self.blocks.borrow_mut();
+
+        while let Some(block) = blocks.pop() {
+            if block.start < row_count {
+                blocks.push(Block {
+                    count: min(block.count, row_count - block.start),
+                    ..block
+                });
+                break;
+            }
+        }
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get_column_count(&self) -> i64 {
+        self.database.query_row("SELECT COUNT(id) FROM columns", &[], |row| {
+            row.get(0)
+        }).unwrap()
+    }
+
+    fn get_columns(&self) -> Vec<String> {
+        let mut stmt = self.database.prepare_cached("SELECT name FROM columns ORDER BY id ASC").unwrap();
+        let mut rows = stmt.query(&[]).unwrap();
+        let mut columns = Vec::new();
+
+        while let Some(Ok(row)) = rows.next() {
+            columns.push(row.get(0));
+        }
+
+        columns
+    }
+
+    fn get_column_types(&self) -> Vec<Option<String>> {
+        let mut stmt = self.database.prepare_cached("SELECT data_type FROM columns ORDER BY id ASC").unwrap();
+        let mut rows = stmt.query(&[]).unwrap();
+        let mut types = Vec::new();
+
+        while let Some(Ok(row)) = rows.next() {
+            types.push(row.get(0));
+        }
+
+        types
+    }
+
+    fn set_column_type(&self, column: i64, data_type: Option<&str>) -> Result<()> {
+        self.database.execute("UPDATE columns SET data_type = ? WHERE id = ?", &[&data_type, &column])?;
+
+        Ok(())
+    }
+
+    fn insert_columns(&self, position: i64, names: Vec<String>) -> Result<()> {
+        let column_count = self.get_column_count();
+
+        // Shift columns to the right to make room for the given column count.
+        let shift_amount = names.len() as i64;
+        self.database.execute("
+            UPDATE columns
+            SET id = id + ?
+            WHERE id >= ?
+        ", &[
+            &shift_amount,
+            &position,
+        ])?;
+
+        // Insert the new columns.
+        let mut stmt = self.database.prepare_cached("INSERT INTO columns (id, name) VALUES (?, ?)")?;
+        for (offset, value) in names.into_iter().enumerate() {
+            let pos = position + offset as i64;
+            stmt.execute(&[&pos, &value])?;
+        }
+
+        // Move the cells of existing rows to the right, too. Rows have no cells past the last column, so there is
+        // nothing to move when adding columns to the end.
+        if position < column_count {
+            let position = position as usize;
+            self.rewrite_rows(|cells| if cells.len() > position {
+                let moved = cells.split_off(position);
+                cells.resize(position + shift_amount as usize, None);
+                cells.extend(moved);
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn rename_column(&self, column: i64, name: &str) -> Result<()> {
+        self.database.execute("UPDATE columns SET name = ? WHERE id = ?", &[&name, &column])?;
+
+        Ok(())
+    }
+
+    fn get_row_count(&self) -> i64 {
+        match self.loading {
+            Some(ref progress) => progress.rows(),
+            None => self.row_count.get(),
+        }
+    }
+
+    fn get_rows(&self, start: i64, end: i64) -> Result<Vec<Vec<Option<String>>>> {
+        let mut stmt = self.database.prepare_cached("
+            SELECT id, cells FROM rows
+            WHERE id >= ? AND id <= ?
+        ")?;
+
+        let end = min(end, self.get_row_count() - 1);
+        let column_count = self.get_column_count() as usize;
+        let mut rows = vec![vec![None; column_count]; max(0, end - start + 1) as usize];
+
+        for (first_row, first_id, last_id) in self.id_ranges(start, end) {
+            let mut results = stmt.query(&[&first_id, &last_id])?;
+
+            while let Some(result) = results.next() {
+                let result = result?;
+                let id: i64 = result.get(0);
+                let blob: Vec<u8> = result.get(1);
+
+                if let Some(row) = rows.get_mut((first_row + id - first_id - start) as usize) {
+                    let cells = decode_cells(&blob)?;
+                    if cells.len() > row.len() {
+                        row.resize(cells.len(), None);
+                    }
+                    for (column, value) in cells.into_iter().enumerate() {
+                        row[column] = value;
+                    }
+                }
+            }
+        }
+
+        Ok(rows)
+    }
+
+    fn get_cell(&self, row: i64, column: i64) -> Option<String> {
+        match self.row_id(row).map_or(Ok(None), |id| self.get_row_cells(id)) {
+            Ok(Some(mut cells)) if column >= 0 && (column as usize) < cells.len() => cells.swap_remove(column as usize),
+            _ => None,
+        }
+    }
+
+    fn set_cell(&self, row: i64, column: i64, value: Option<String>) -> Result<()> {
+        let id = match self.row_id(row) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        if let Some(mut cells) = self.get_row_cells(id)? {
+            if column as usize >= cells.len() {
+                cells.resize(column as usize + 1, None);
+            }
+            cells[column as usize] = value;
+
+            self.set_row_cells(id, &cells)?;
+        }
+
+        Ok(())
+    }
+
+    fn insert_row(&self, row: i64, values: Vec<Option<String>>) -> Result<()> {
+        // Shift the rows below down by one, within the block the row goes in.
+        let id = if row < self.row_count.get() {
+            self.make_room(max(0, row))?
+        } else {
+            self.append_row()
+        };
+
+        self.database.execute("INSERT INTO rows (id, cells) VALUES (?, ?)", &[&id, &encode_cells(values)])?;
+        self.row_count.set(self.row_count.get() + 1);
+
+        Ok(())
+    }
+
+    fn bulk_insert<'a>(&'a self) -> Result<Box<BulkWriter + 'a>> {
+        let journal_mode: String = self.database.query_row("PRAGMA journal_mode", &[], |row| row.get(0))?;
+        let cache_size: i64 = self.database.query_row("PRAGMA cache_size", &[], |row| row.get(0))?;
+
+        // Keep the rollback journal in memory instead of writing it out with every transaction. A database that is
+        // read while it loads must keep its write-ahead log.
+        if journal_mode != "wal" {
+            self.database.execute_batch("PRAGMA journal_mode = MEMORY")?;
+        }
+
+        self.database.execute_batch(&format!("
+            PRAGMA cache_size = -{};
+            BEGIN;
+        ", LOAD_CACHE_SIZE))?;
+
+        Ok(Box::new(SqliteBulkWriter {
+            storage: self,
+            journal_mode: journal_mode,
+            cache_size: cache_size,
+            rows: Vec::with_capacity(ROWS_PER_STATEMENT),
+            pending_rows: 0,
+            pending_cells: 0,
+            finished: false,
+        }))
+    }
+
+    fn delete_rows(&self, start: i64, end: i64) -> Result<()> {
+        // Delete from the last block first, so that finding the blocks of the rows before it is not affected.
+        for (first_row, first_id, last_id) in self.id_ranges(start, end).into_iter().rev() {
+            let index = find_block(&self.blocks.borrow(), first_row);
+            let block = self.blocks.borrow()[index];
+            let count = last_id - first_id + 1;
+
+            self.database.execute("
+                DELETE FROM rows
+                WHERE id >= ? AND id <= ?
+            ", &[
+                &first_id,
+                &last_id,
+            ])?;
+
+            // Shift the rows after the deleted range in the block back up.
+            self.shift_rows(last_id + 1, block.first_id + block.count, -count)?;
+            self.blocks.borrow_mut()[index].count -= count;
+            self.row_count.set(self.row_count.get() - count);
+        }
+
+        renumber_blocks(&mut self.blocks.borrow_mut());
+
+        Ok(())
+    }
+
+    fn get_row_keys(&self, start: i64, end: i64) -> Result<Vec<Option<String>>> {
+        let mut stmt = self.database.prepare_cached("
+            SELECT id, key FROM rows
+            WHERE id >= ? AND id <= ? AND key IS NOT NULL
+        ")?;
+
+        let mut keys = vec![None; max(0, end - start + 1) as usize];
+
+        for (first_row, first_id, last_id) in self.id_ranges(start, end) {
+            let mut results = stmt.query(&[&first_id, &last_id])?;
+
+            while let Some(result) = results.next() {
+                let result = result?;
+                let id: i64 = result.get(0);
+                keys[(first_row + id - first_id - start) as usize] = Some(result.get(1));
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn set_row_key(&self, row: i64, key: Option<&str>) -> Result<()> {
+        if let Some(id) = self.row_id(row) {
+            self.database.execute("UPDATE rows SET key = ? WHERE id = ?", &[&key, &id])?;
+        }
+
+        Ok(())
+    }
+
+    fn progress(&self) -> Option<&Arc<LoadProgress>> {
+        self.loading.as_ref()
+    }
+}
+
+
+/// Appends rows to the end of a database quickly. Created by `SqliteStorage::bulk_insert`.
+///
+/// Until the writer is finished, the database is tuned for writing and holds the rows in an open transaction. Rows are
+/// inserted by statements of many rows at once, and committed in large transactions. Rows become part of the database
+/// as their transaction is committed, and the last of them once the writer is finished. Dropping the writer without
+/// finishing it discards the rows not yet committed.
+struct SqliteBulkWriter<'a> {
+    storage: &'a SqliteStorage,
+
+    /// Database settings to restore once the rows are inserted.
+    journal_mode: String,
+    cache_size: i64,
+
+    /// Rows waiting to be inserted, as their ID, key, and encoded cells.
+    rows: Vec<(i64, Option<String>, Vec<u8>)>,
+
+    /// Number of rows added since the last commit.
+    pending_rows: i64,
+
+    /// Number of cells added since the last commit.
+    pending_cells: usize,
+
+    /// Whether the writer was finished.
+    finished: bool,
+}
+
+impl<'a> SqliteBulkWriter<'a> {
+    /// Insert the rows waiting to be inserted, in a single statement.
+    fn insert_rows(&mut self) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let placeholders = vec!["(?, ?, ?)"; self.rows.len()].join(", ");
+        let sql = format!("INSERT INTO rows (id, key, cells) VALUES {}", placeholders);
+        let mut params: Vec<&ToSql> = Vec::with_capacity(self.rows.len() * 3);
+        for &(ref id, ref key, ref cells) in &self.rows {
+            params.push(id);
+            params.push(key);
+            params.push(cells);
+        }
+
+        self.storage.database.prepare_cached(&sql)?.execute(&params)?;
+        self.rows.clear();
+
+        Ok(())
+    }
+
+    /// Commit the rows added so far, ending the current transaction.
+    fn commit(&mut self) -> Result<()> {
+        self.insert_rows()?;
+        self.storage.database.execute_batch("COMMIT")?;
+
+        if self.pending_rows > 0 {
+            self.storage.row_count.set(self.storage.row_count.get() + self.pending_rows);
+
+            if let Some(ref progress) = self.storage.filling {
+                progress.add_rows(self.pending_rows as usize);
+            }
+        }
+
+        self.pending_rows = 0;
+        self.pending_cells = 0;
+
+        Ok(())
+    }
+
+    /// Restore the database settings changed for inserting rows.
+    fn restore_settings(&self) -> Result<()> {
+        if self.journal_mode != "wal" {
+            self.storage.database.execute_batch(&format!("PRAGMA journal_mode = {}", self.journal_mode))?;
+        }
+        self.storage.database.execute_batch(&format!("PRAGMA cache_size = {}", self.cache_size))?;
+
+        Ok(())
+    }
+}
+
+impl<'a> BulkWriter for SqliteBulkWriter<'a> {
+    fn push(&mut self, values: Vec<Option<String>>, key: Option<String>) -> Result<()> {
+        let id = self.storage.append_row();
+        self.pending_rows += 1;
+        self.pending_cells += values.len();
+        self.rows.push((id, key, encode_cells(values)));
+
+        if self.rows.len() == ROWS_PER_STATEMENT {
+            self.insert_rows()?;
+        }
+
+        if self.pending_cells >= CELLS_PER_TRANSACTION {
+            self.commit()?;
+            self.storage.database.execute_batch("BEGIN")?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.commit()?;
+        self.restore_settings()?;
+        self.finished = true;
+
+        Ok(())
+    }
+}
+
+impl<'a> Drop for SqliteBulkWriter<'a> {
+    fn drop(&mut self) {
+        // The load failed, so the rows of the current transaction are discarded.
+        if !self.finished {
+            let _ = self.storage.database.execute_batch("ROLLBACK");
+            let _ = self.restore_settings();
+            self.storage.truncate_blocks(self.storage.row_count.get());
+        }
+    }
+}
+
+
+/// Find the index of the block holding a row, or of the last block if the row is past the end.
+fn find_block(blocks: &[Block], row: i64) -> usize {
+    match blocks.binary_search_by_key(&row, |block| block.start) {
+        Ok(index) => index,
+        Err(index) => index.saturating_sub(1),
+    }
+}
+
+/// Remove the blocks left empty, and number the rows of each block again after rows were added or removed.
+fn renumber_blocks(blocks: &mut Vec<Block>) {
+    blocks.retain(|block| block.count > 0);
+
+    let mut start = 0;
+    for block in blocks.iter_mut() {
+        block.start = start;
+        start += block.count;
+    }
+}
+
+/// Encode the cells of a row for storing in the database. Each cell is written as the length of its value in bytes
+/// plus one, or zero if it is empty, as a little-endian `u32` followed by the value's UTF-8 text.
+fn encode_cells<S: Into<Option<String>>, I: IntoIterator<Item = S>>(values: I) -> Vec<u8> {
+    let mut blob = Vec::new();
+    let mut length = [0; 4];
+
+    for value in values {
+        match value.into() {
+            Some(value) => {
+                LittleEndian::write_u32(&mut length, value.len() as u32 + 1);
+                blob.extend_from_slice(&length);
+                blob.extend_from_slice(value.as_bytes());
+            }
+            None => blob.extend_from_slice(&[0; 4]),
+        }
+    }
+
+    blob
+}
+
+/// Decode the cells of a row stored in the database.
+fn decode_cells(mut blob: &[u8]) -> Result<Vec<Option<String>>> {
+    let mut cells = Vec::new();
+
+    while !blob.is_empty() {
+        if blob.len() < 4 {
+            return Err("The cells of a row are corrupted.".into());
+        }
+
+        let length = LittleEndian::read_u32(blob) as usize;
+        blob = &blob[4..];
+
+        if length == 0 {
+            cells.push(None);
+        } else if blob.len() < length - 1 {
+            return Err("The cells of a row are corrupted.".into());
+        } else {
+            cells.push(Some(str::from_utf8(&blob[..length - 1])?.to_string()));
+            blob = &blob[length - 1..];
+        }
+    }
+
+    Ok(cells)
+}
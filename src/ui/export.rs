@@ -0,0 +1,224 @@
+use formats::{self, JsonLayout, JsonOptions, ParquetCompression, ParquetOptions, SqlDialect, SqlOptions};
+use gtk::*;
+use std::path::*;
+
+
+/// How the user chose to write a file.
+#[derive(Clone, Debug)]
+pub enum ExportFormat {
+    /// A format without options, written as given by the file extension.
+    Default,
+    Json(JsonOptions),
+    Parquet(ParquetOptions),
+    /// A SQL script creating the named table.
+    Sql(String, SqlOptions),
+    /// A table of a SQLite database, by name.
+    SqliteTable(String),
+}
+
+
+/// Dialog for choosing the options of the format a file is saved in, for formats that have any.
+#[derive(Clone)]
+pub struct ExportDialog {
+    dialog: Dialog,
+    json_grid: Grid,
+    json_layout_combo: ComboBoxText,
+    json_pretty_check: CheckButton,
+    json_typed_check: CheckButton,
+    json_nulls_check: CheckButton,
+    parquet_grid: Grid,
+    parquet_compression_combo: ComboBoxText,
+    sql_grid: Grid,
+    sql_table_entry: Entry,
+    sql_dialect_label: Label,
+    sql_dialect_combo: ComboBoxText,
+}
+
+impl ExportDialog {
+    pub fn new(builder: &Builder) -> Self {
+        let export = Self {
+            dialog: builder.get_object("export_dialog").unwrap(),
+            json_grid: builder.get_object("export_json_grid").unwrap(),
+            json_layout_combo: builder.get_object("export_json_layout_combo").unwrap(),
+            json_pretty_check: builder.get_object("export_json_pretty_check").unwrap(),
+            json_typed_check: builder.get_object("export_json_typed_check").unwrap(),
+            json_nulls_check: builder.get_object("export_json_nulls_check").unwrap(),
+            parquet_grid: builder.get_object("export_parquet_grid").unwrap(),
+            parquet_compression_combo: builder.get_object("export_parquet_compression_combo").unwrap(),
+            sql_grid: builder.get_object("export_sql_grid").unwrap(),
+            sql_table_entry: builder.get_object("export_sql_table_entry").unwrap(),
+            sql_dialect_label: builder.get_object("export_sql_dialect_label").unwrap(),
+            sql_dialect_combo: builder.get_object("export_sql_dialect_combo").unwrap(),
+        };
+
+        // Records written one per line cannot be indented.
+        {
+            let cloned = export.clone();
+            export.json_layout_combo.connect_changed(move |_| {
+                cloned.json_pretty_check.set_sensitive(cloned.get_json_layout() != Some(JsonLayout::Lines));
+            });
+        }
+
+        export
+    }
+
+    /// Show the dialog for the format of a file, if it has any options, starting with the defaults for its extension.
+    /// Database tables are named `table` unless the user chooses another name.
+    ///
+    /// Returns how the user chose to write the file, or `None` if saving was cancelled.
+    pub fn run<P: AsRef<Path>>(&self, path: P, table: &str) -> Option<ExportFormat> {
+        let format = match formats::uncompressed_extension(path.as_ref()).as_ref().map(String::as_str) {
+            Some("json") => ExportFormat::Json(JsonOptions::default()),
+            Some("jsonl") | Some("ndjson") => ExportFormat::Json(JsonOptions {
+                layout: JsonLayout::Lines,
+                ..JsonOptions::default()
+            }),
+            Some("parquet") => ExportFormat::Parquet(ParquetOptions::default()),
+            Some("sql") => ExportFormat::Sql(table.to_string(), SqlOptions::default()),
+            Some("db") | Some("sqlite") | Some("sqlite3") => ExportFormat::SqliteTable(table.to_string()),
+            _ => return Some(ExportFormat::Default),
+        };
+
+        self.set_format(&format);
+
+        let response = self.dialog.run();
+        self.dialog.hide();
+
+        let ok: i32 = ResponseType::Ok.into();
+        if response != ok {
+            return None;
+        }
+
+        Some(self.get_format(&format))
+    }
+
+    /// Show the options of a format, set to the given values.
+    fn set_format(&self, format: &ExportFormat) {
+        self.json_grid.set_visible(false);
+        self.parquet_grid.set_visible(false);
+        self.sql_grid.set_visible(false);
+
+        match *format {
+            ExportFormat::Default => {}
+            ExportFormat::Json(options) => {
+                self.json_layout_combo.set_active_id(json_layout_id(options.layout));
+                self.json_pretty_check.set_active(options.pretty);
+                self.json_typed_check.set_active(options.typed);
+                self.json_nulls_check.set_active(options.nulls);
+                self.json_grid.set_visible(true);
+            }
+            ExportFormat::Parquet(options) => {
+                self.parquet_compression_combo.set_active_id(parquet_compression_id(options.compression));
+                self.parquet_grid.set_visible(true);
+            }
+            ExportFormat::Sql(ref table, options) => {
+                self.sql_table_entry.set_text(table);
+                self.sql_dialect_combo.set_active_id(sql_dialect_id(options.dialect));
+                self.sql_dialect_label.set_visible(true);
+                self.sql_dialect_combo.set_visible(true);
+                self.sql_grid.set_visible(true);
+            }
+            ExportFormat::SqliteTable(ref table) => {
+                self.sql_table_entry.set_text(table);
+                self.sql_dialect_label.set_visible(false);
+                self.sql_dialect_combo.set_visible(false);
+                self.sql_grid.set_visible(true);
+            }
+        }
+    }
+
+    /// Get the options chosen for a format.
+    fn get_format(&self, format: &ExportFormat) -> ExportFormat {
+        match *format {
+            ExportFormat::Default => ExportFormat::Default,
+            ExportFormat::Json(options) => ExportFormat::Json(JsonOptions {
+                layout: self.get_json_layout().unwrap_or(options.layout),
+                pretty: self.json_pretty_check.get_active(),
+                typed: self.json_typed_check.get_active(),
+                nulls: self.json_nulls_check.get_active(),
+                ..options
+            }),
+            ExportFormat::Parquet(options) => ExportFormat::Parquet(ParquetOptions {
+                compression: self.parquet_compression_combo.get_active_id()
+                    .and_then(|id| parquet_compression_from_id(&id))
+                    .unwrap_or(options.compression),
+                ..options
+            }),
+            ExportFormat::Sql(_, options) => ExportFormat::Sql(self.get_table(), SqlOptions {
+                dialect: self.sql_dialect_combo.get_active_id()
+                    .and_then(|id| sql_dialect_from_id(&id))
+                    .unwrap_or(options.dialect),
+                ..options
+            }),
+            ExportFormat::SqliteTable(_) => ExportFormat::SqliteTable(self.get_table()),
+        }
+    }
+
+    /// Get the table name entered by the user.
+    fn get_table(&self) -> String {
+        self.sql_table_entry.get_text().unwrap_or_default().trim().to_string()
+    }
+
+    /// Get the JSON layout chosen from the list.
+    fn get_json_layout(&self) -> Option<JsonLayout> {
+        self.json_layout_combo.get_active_id().and_then(|id| json_layout_from_id(&id))
+    }
+}
+
+
+/// Get the list ID for a JSON layout.
+fn json_layout_id(layout: JsonLayout) -> Option<&'static str> {
+    match layout {
+        JsonLayout::Array => Some("array"),
+        JsonLayout::Lines => Some("lines"),
+    }
+}
+
+/// Get the JSON layout for a list ID.
+fn json_layout_from_id(id: &str) -> Option<JsonLayout> {
+    match id {
+        "array" => Some(JsonLayout::Array),
+        "lines" => Some(JsonLayout::Lines),
+        _ => None,
+    }
+}
+
+/// Get the list ID for a Parquet compression codec.
+fn parquet_compression_id(compression: ParquetCompression) -> Option<&'static str> {
+    match compression {
+        ParquetCompression::Uncompressed => Some("uncompressed"),
+        ParquetCompression::Snappy => Some("snappy"),
+        ParquetCompression::Gzip => Some("gzip"),
+        ParquetCompression::Zstd => Some("zstd"),
+    }
+}
+
+/// Get the Parquet compression codec for a list ID.
+fn parquet_compression_from_id(id: &str) -> Option<ParquetCompression> {
+    match id {
+        "uncompressed" => Some(ParquetCompression::Uncompressed),
+        "snappy" => Some(ParquetCompression::Snappy),
+        "gzip" => Some(ParquetCompression::Gzip),
+        "zstd" => Some(ParquetCompression::Zstd),
+        _ => None,
+    }
+}
+
+/// Get the list ID for a SQL dialect.
+fn sql_dialect_id(dialect: SqlDialect) -> Option<&'static str> {
+    match dialect {
+        SqlDialect::Sqlite => Some("sqlite"),
+        SqlDialect::Postgres => Some("postgres"),
+        SqlDialect::MySql => Some("mysql"),
+    }
+}
+
+/// Get the SQL dialect for a list ID.
+fn sql_dialect_from_id(id: &str) -> Option<SqlDialect> {
+    match id {
+        "sqlite" => Some(SqlDialect::Sqlite),
+        "postgres" => Some(SqlDialect::Postgres),
+        "mysql" => Some(SqlDialect::MySql),
+        _ => None,
+    }
+}
@@ -0,0 +1,509 @@
+use encoding_rs::Encoding;
+use formats::{self, ColumnNaming, Dialect, FixedWidthLayout, RaggedRows};
+use gtk::*;
+use super::populate_encodings;
+use std::cell::{Cell, RefCell};
+use std::path::*;
+use std::rc::Rc;
+
+
+/// Number of rows to show in the import preview.
+const PREVIEW_ROWS: usize = 200;
+
+/// Number of lines of a fixed-width file to show under the ruler.
+const RULER_LINES: usize = 50;
+
+
+/// How the user chose to read a text file.
+#[derive(Clone, Debug)]
+pub enum ImportFormat {
+    Delimited(Dialect),
+    FixedWidth(FixedWidthLayout),
+    /// Delimited text viewed in place, without importing it.
+    Indexed(Dialect),
+}
+
+
+/// Dialog for choosing how a delimited or fixed-width text file is read before loading it, with a live preview of the
+/// result.
+#[derive(Clone)]
+pub struct ImportDialog {
+    dialog: Dialog,
+    delimiter_combo: ComboBoxText,
+    quote_combo: ComboBoxText,
+    encoding_combo: ComboBoxText,
+    headers_check: CheckButton,
+    column_naming_combo: ComboBoxText,
+    ragged_rows_combo: ComboBoxText,
+    skip_lines_entry: SpinButton,
+    view_check: CheckButton,
+    status_label: Label,
+    ruler_window: ScrolledWindow,
+    ruler_view: TextView,
+    preview_view: TreeView,
+    path: Rc<RefCell<Option<PathBuf>>>,
+    dialect: Rc<Cell<Dialect>>,
+    /// Offsets at which fixed-width columns start, or `None` until they are first guessed from the file.
+    boundaries: Rc<RefCell<Option<Vec<usize>>>>,
+    updating: Rc<Cell<bool>>,
+}
+
+impl ImportDialog {
+    pub fn new(builder: &Builder) -> Self {
+        let import = Self {
+            dialog: builder.get_object("import_dialog").unwrap(),
+            delimiter_combo: builder.get_object("import_delimiter_combo").unwrap(),
+            quote_combo: builder.get_object("import_quote_combo").unwrap(),
+            encoding_combo: builder.get_object("import_encoding_combo").unwrap(),
+            headers_check: builder.get_object("import_headers_check").unwrap(),
+            column_naming_combo: builder.get_object("import_column_naming_combo").unwrap(),
+            ragged_rows_combo: builder.get_object("import_ragged_rows_combo").unwrap(),
+            skip_lines_entry: builder.get_object("import_skip_lines_entry").unwrap(),
+            view_check: builder.get_object("import_view_check").unwrap(),
+            status_label: builder.get_object("import_status_label").unwrap(),
+            ruler_window: builder.get_object("import_ruler_window").unwrap(),
+            ruler_view: builder.get_object("import_ruler_view").unwrap(),
+            preview_view: builder.get_object("import_preview_view").unwrap(),
+            path: Rc::new(RefCell::new(None)),
+            dialect: Rc::new(Cell::new(Dialect::default())),
+            boundaries: Rc::new(RefCell::new(None)),
+            updating: Rc::new(Cell::new(false)),
+        };
+
+        populate_encodings(&import.encoding_combo);
+
+        // Refresh the preview whenever an option changes.
+        {
+            let cloned = import.clone();
+            import.delimiter_combo.connect_changed(move |_| cloned.update_preview());
+        }
+
+        {
+            let cloned = import.clone();
+            import.quote_combo.connect_changed(move |_| cloned.update_preview());
+        }
+
+        {
+            let cloned = import.clone();
+            import.encoding_combo.connect_changed(move |_| cloned.update_preview());
+        }
+
+        {
+            let cloned = import.clone();
+            import.headers_check.connect_toggled(move |_| cloned.update_preview());
+        }
+
+        {
+            let cloned = import.clone();
+            import.column_naming_combo.connect_changed(move |_| cloned.update_preview());
+        }
+
+        {
+            let cloned = import.clone();
+            import.skip_lines_entry.connect_value_changed(move |_| cloned.update_preview());
+        }
+
+        {
+            let cloned = import.clone();
+            import.view_check.connect_toggled(move |_| cloned.update_preview());
+        }
+
+        // Clicking on the fixed-width ruler adds or removes a column boundary.
+        {
+            let cloned = import.clone();
+            import.ruler_view.connect_button_press_event(move |view, event| {
+                if event.get_button() == 1 {
+                    let (x, y) = event.get_position();
+                    let (x, _) = view.window_to_buffer_coords(TextWindowType::Text, x as i32, y as i32);
+
+                    // Use the ruler line, since it is as long as the longest line.
+                    if let Some(iter) = view.get_iter_at_location(x, 0) {
+                        if !iter.ends_line() {
+                            cloned.toggle_boundary(iter.get_line_offset() as usize);
+                        }
+                    }
+                }
+
+                Inhibit(true)
+            });
+        }
+
+        import
+    }
+
+    /// Show the dialog for a file, starting with the given dialect, or with fixed-width columns if `fixed_width` is
+    /// set.
+    ///
+    /// Returns how the user chose to read the file, or `None` if the import was cancelled.
+    pub fn run<P: AsRef<Path>>(&self, path: P, dialect: Dialect, fixed_width: bool) -> Option<ImportFormat> {
+        *self.path.borrow_mut() = Some(path.as_ref().to_path_buf());
+        self.dialect.set(dialect);
+        *self.boundaries.borrow_mut() = None;
+
+        // Populate the options without refreshing the preview for each one.
+        self.updating.set(true);
+        if fixed_width {
+            self.delimiter_combo.set_active_id(Some("fixed"));
+        } else {
+            self.set_delimiter(dialect.delimiter);
+        }
+        self.quote_combo.set_active_id(quote_id(dialect.quote));
+        self.encoding_combo.set_active_id(dialect.encoding.name());
+        self.headers_check.set_active(dialect.has_headers);
+        self.column_naming_combo.set_active_id(column_naming_id(dialect.column_naming));
+        self.ragged_rows_combo.set_active_id(ragged_rows_id(dialect.ragged_rows));
+        self.skip_lines_entry.set_value(dialect.skip_lines as f64);
+        self.view_check.set_active(false);
+        self.updating.set(false);
+
+        self.update_preview();
+
+        let response = self.dialog.run();
+        self.dialog.hide();
+
+        let ok: i32 = ResponseType::Ok.into();
+        if response != ok {
+            None
+        } else if self.is_fixed_width() {
+            Some(ImportFormat::FixedWidth(self.get_layout()))
+        } else if self.view_check.get_active() && self.view_check.is_sensitive() {
+            Some(ImportFormat::Indexed(self.get_dialect()))
+        } else {
+            Some(ImportFormat::Delimited(self.get_dialect()))
+        }
+    }
+
+    /// Check if the file is to be read as fixed-width columns instead of delimited fields.
+    fn is_fixed_width(&self) -> bool {
+        self.delimiter_combo.get_active_id().map_or(false, |id| id == "fixed")
+    }
+
+    /// Get the dialect described by the current options.
+    fn get_dialect(&self) -> Dialect {
+        let mut dialect = self.dialect.get();
+
+        if let Some(delimiter) = self.get_delimiter() {
+            dialect.delimiter = delimiter;
+        }
+
+        if let Some(quote) = self.quote_combo.get_active_id().and_then(|id| quote_from_id(&id)) {
+            dialect.quote = quote;
+        }
+
+        if let Some(encoding) = self.encoding_combo.get_active_id().and_then(|id| Encoding::for_label(id.as_bytes())) {
+            dialect.encoding = encoding;
+        }
+
+        dialect.has_headers = self.headers_check.get_active();
+        let column_naming = self.column_naming_combo.get_active_id().and_then(|id| column_naming_from_id(&id));
+        if let Some(column_naming) = column_naming {
+            dialect.column_naming = column_naming;
+        }
+        if let Some(ragged_rows) = self.ragged_rows_combo.get_active_id().and_then(|id| ragged_rows_from_id(&id)) {
+            dialect.ragged_rows = ragged_rows;
+        }
+        dialect.skip_lines = self.skip_lines_entry.get_value_as_int() as usize;
+
+        dialect
+    }
+
+    /// Get the fixed-width layout described by the current options and column boundaries.
+    fn get_layout(&self) -> FixedWidthLayout {
+        let dialect = self.get_dialect();
+        let boundaries = self.boundaries.borrow().clone().unwrap_or_default();
+
+        FixedWidthLayout {
+            has_headers: dialect.has_headers,
+            column_naming: dialect.column_naming,
+            skip_lines: dialect.skip_lines,
+            encoding: dialect.encoding,
+            ..FixedWidthLayout::from_boundaries(&boundaries)
+        }
+    }
+
+    /// Start a fixed-width column at an offset, or remove the boundary if a column already starts there.
+    fn toggle_boundary(&self, offset: usize) {
+        if offset == 0 {
+            return;
+        }
+
+        {
+            let mut boundaries = self.boundaries.borrow_mut();
+            let boundaries = boundaries.get_or_insert_with(Vec::new);
+
+            match boundaries.binary_search(&offset) {
+                Ok(index) => {
+                    boundaries.remove(index);
+                }
+                Err(index) => boundaries.insert(index, offset),
+            }
+        }
+
+        self.update_preview();
+    }
+
+    /// Get the delimiter chosen from the list, or typed in by the user.
+    fn get_delimiter(&self) -> Option<u8> {
+        if let Some(id) = self.delimiter_combo.get_active_id() {
+            return delimiter_from_id(&id);
+        }
+
+        // A custom delimiter must be a single ASCII character.
+        match self.delimiter_combo.get_active_text() {
+            Some(ref text) if text.len() == 1 => Some(text.as_bytes()[0]),
+            _ => None,
+        }
+    }
+
+    /// Select a delimiter in the list, or enter it as a custom delimiter if it is not in the list.
+    fn set_delimiter(&self, delimiter: u8) {
+        if !self.delimiter_combo.set_active_id(delimiter_id(delimiter)) {
+            if let Some(entry) = self.delimiter_combo.get_child() {
+                let entry: Entry = entry.downcast().unwrap();
+                entry.set_text(&(delimiter as char).to_string());
+            }
+        }
+    }
+
+    /// Re-read the beginning of the file with the current options and display it.
+    fn update_preview(&self) {
+        if self.updating.get() {
+            return;
+        }
+
+        // Remove the previous preview.
+        self.preview_view.set_model::<TreeModel>(None);
+        for column in self.preview_view.get_columns() {
+            self.preview_view.remove_column(&column);
+        }
+
+        let path = match self.path.borrow().clone() {
+            Some(path) => path,
+            None => return,
+        };
+
+        // Quotes have no meaning in fixed-width files, which are split with the ruler instead, so every line has a
+        // field for each column. Files viewed in place show every record as a row of its own, so ragged rows are kept
+        // as they are. Only uncompressed delimited files can be viewed in place.
+        let fixed_width = self.is_fixed_width();
+        let viewable = !fixed_width && formats::Compression::for_path(&path).is_none();
+        self.view_check.set_sensitive(viewable);
+        self.quote_combo.set_sensitive(!fixed_width);
+        self.ragged_rows_combo.set_sensitive(!fixed_width && !(viewable && self.view_check.get_active()));
+        self.column_naming_combo.set_sensitive(!self.headers_check.get_active());
+        self.ruler_window.set_visible(fixed_width);
+
+        if fixed_width {
+            self.update_fixed_width_preview(&path);
+            return;
+        }
+
+        if self.get_delimiter().is_none() {
+            self.status_label.set_text("The delimiter must be a single character.");
+            return;
+        }
+
+        let (columns, rows) = match formats::preview_delimited(&path, &self.get_dialect(), PREVIEW_ROWS) {
+            Ok(preview) => preview,
+            Err(e) => {
+                self.status_label.set_text(&format!("Unable to read file: {}", e));
+                return;
+            }
+        };
+
+        self.show_rows(&columns, &rows);
+    }
+
+    /// Re-read the beginning of the file as fixed-width columns, and display it under the ruler and split into columns.
+    fn update_fixed_width_preview(&self, path: &Path) {
+        let mut lines = match formats::preview_fixed_width(path, &self.get_layout(), PREVIEW_ROWS + 1) {
+            Ok(lines) => lines,
+            Err(e) => {
+                self.show_ruler(&[], &[]);
+                self.status_label.set_text(&format!("Unable to read file: {}", e));
+                return;
+            }
+        };
+
+        // Start with the columns that line up across the file, until the user chooses their own.
+        if self.boundaries.borrow().is_none() {
+            *self.boundaries.borrow_mut() = Some(formats::guess_boundaries(&lines));
+        }
+
+        let layout = self.get_layout();
+        self.show_ruler(&lines, &layout.boundaries());
+
+        let headers = if layout.has_headers && !lines.is_empty() {
+            layout.split(&lines.remove(0))
+        } else {
+            Vec::new()
+        };
+        let columns = (0..layout.columns.len())
+            .map(|index| match headers.get(index) {
+                Some(name) if !name.is_empty() => name.clone(),
+                _ => layout.column_naming.name(index),
+            })
+            .collect::<Vec<_>>();
+        lines.truncate(PREVIEW_ROWS);
+
+        let rows = lines.iter().map(|line| layout.split(line)).collect::<Vec<_>>();
+        self.show_rows(&columns, &rows);
+    }
+
+    /// Display the first lines of a fixed-width file under a ruler, with a marker where each column starts.
+    fn show_ruler(&self, lines: &[String], boundaries: &[usize]) {
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) + 1;
+
+        // Number every tenth character, and mark every fifth.
+        let mut text = (1..width + 1)
+            .map(|position| if position % 10 == 0 {
+                (b'0' + (position / 10 % 10) as u8) as char
+            } else if position % 5 == 0 {
+                '+'
+            } else {
+                '-'
+            })
+            .collect::<String>();
+        text.push('\n');
+
+        text.extend((0..width).map(|offset| if boundaries.contains(&offset) { '|' } else { ' ' }));
+
+        for line in lines.iter().take(RULER_LINES) {
+            text.push('\n');
+            text.push_str(line);
+        }
+
+        if let Some(buffer) = self.ruler_view.get_buffer() {
+            buffer.set_text(&text);
+        }
+    }
+
+    /// Display preview rows in the table, under the given column names.
+    fn show_rows(&self, columns: &[String], rows: &[Vec<String>]) {
+        // Ragged rows may have more fields than there are columns.
+        let column_count = rows.iter()
+            .map(|row| row.len())
+            .chain(Some(columns.len()))
+            .max()
+            .unwrap_or(0);
+
+        // Row number column.
+        {
+            let column = TreeViewColumn::new();
+            column.set_title("#");
+
+            let renderer = CellRendererText::new();
+            column.pack_start(&renderer, true);
+            column.add_attribute(&renderer, "text", 0);
+
+            self.preview_view.append_column(&column);
+        }
+
+        for index in 0..column_count {
+            let column = TreeViewColumn::new();
+            column.set_resizable(true);
+            column.set_title(columns.get(index).map(String::as_str).unwrap_or(""));
+
+            let renderer = CellRendererText::new();
+            column.pack_start(&renderer, true);
+            column.add_attribute(&renderer, "text", index as i32 + 1);
+
+            self.preview_view.append_column(&column);
+        }
+
+        let column_types = vec![Type::String; column_count + 1];
+        let model = ListStore::new(&column_types);
+        for (row_num, row) in rows.iter().enumerate() {
+            let iter = model.append();
+            model.set_value(&iter, 0, &(row_num as i64 + 1).to_value());
+
+            for (column, value) in row.iter().enumerate() {
+                model.set_value(&iter, column as u32 + 1, &value.to_value());
+            }
+        }
+        self.preview_view.set_model(Some(&model));
+
+        self.status_label.set_text(&format!("Showing the first {} rows.", rows.len()));
+    }
+}
+
+
+/// Get the list ID for a delimiter.
+fn delimiter_id(delimiter: u8) -> Option<&'static str> {
+    match delimiter {
+        b',' => Some("comma"),
+        b'\t' => Some("tab"),
+        b';' => Some("semicolon"),
+        b'|' => Some("pipe"),
+        b' ' => Some("space"),
+        _ => None,
+    }
+}
+
+/// Get the delimiter for a list ID.
+fn delimiter_from_id(id: &str) -> Option<u8> {
+    match id {
+        "comma" => Some(b','),
+        "tab" => Some(b'\t'),
+        "semicolon" => Some(b';'),
+        "pipe" => Some(b'|'),
+        "space" => Some(b' '),
+        _ => None,
+    }
+}
+
+/// Get the list ID for a quote character.
+fn quote_id(quote: u8) -> Option<&'static str> {
+    match quote {
+        b'"' => Some("double"),
+        b'\'' => Some("single"),
+        _ => None,
+    }
+}
+
+/// Get the quote character for a list ID.
+fn quote_from_id(id: &str) -> Option<u8> {
+    match id {
+        "double" => Some(b'"'),
+        "single" => Some(b'\''),
+        _ => None,
+    }
+}
+
+/// Get the list ID for a column naming scheme.
+fn column_naming_id(column_naming: ColumnNaming) -> Option<&'static str> {
+    match column_naming {
+        ColumnNaming::Numbered => Some("numbered"),
+        ColumnNaming::Lettered => Some("lettered"),
+    }
+}
+
+/// Get the column naming scheme for a list ID.
+fn column_naming_from_id(id: &str) -> Option<ColumnNaming> {
+    match id {
+        "numbered" => Some(ColumnNaming::Numbered),
+        "lettered" => Some(ColumnNaming::Lettered),
+        _ => None,
+    }
+}
+
+/// Get the list ID for a ragged rows policy.
+fn ragged_rows_id(ragged_rows: RaggedRows) -> Option<&'static str> {
+    match ragged_rows {
+        RaggedRows::Pad => Some("pad"),
+        RaggedRows::Truncate => Some("truncate"),
+        RaggedRows::AddColumns => Some("add_columns"),
+        RaggedRows::Reject => Some("reject"),
+    }
+}
+
+/// Get the ragged rows policy for a list ID.
+fn ragged_rows_from_id(id: &str) -> Option<RaggedRows> {
+    match id {
+        "pad" => Some(RaggedRows::Pad),
+        "truncate" => Some(RaggedRows::Truncate),
+        "add_columns" => Some(RaggedRows::AddColumns),
+        "reject" => Some(RaggedRows::Reject),
+        _ => None,
+    }
+}
@@ -0,0 +1,1024 @@
+mod export;
+mod import;
+mod sheets;
+
+use encoding_rs::Encoding;
+use formats::{self, DiagnosticKind, Dialect, JsonOptions};
+use gio::{Menu, SimpleAction};
+use gio::prelude::*;
+use gtk::*;
+use Result;
+use self::export::{ExportDialog, ExportFormat};
+use self::import::{ImportDialog, ImportFormat};
+use self::sheets::SheetDialog;
+use progress::LoadProgress;
+use spreadsheet::Spreadsheet;
+use std::boxed::Box;
+use std::cell::{Cell, RefCell};
+use std::cmp::{max, min};
+use std::error::Error;
+use std::path::*;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+
+const PAGE_SIZE: i64 = 1000;
+
+/// Number of problems to list when reporting problems found while loading a file.
+const REPORT_PROBLEMS: usize = 20;
+
+/// Number of lines to list for each kind of problem when reporting problems found while loading a file.
+const REPORT_LINES: usize = 50;
+
+/// Milliseconds between updates of the progress of a file loading in the background.
+const LOAD_POLL_INTERVAL: u32 = 200;
+
+
+pub fn build_app_menu() -> Menu {
+    let menu = Menu::new();
+
+    menu.append("About Astinus", "app.about");
+    menu.append("Quit", "app.quit");
+
+    menu
+}
+
+pub fn build_window_menu() -> Menu {
+    let menu = Menu::new();
+
+    let file_menu = Menu::new();
+    file_menu.append("New File", "win.new");
+    file_menu.append("Open...", "win.open");
+    file_menu.append("Save As...", "win.save");
+    file_menu.append("Close", "win.close");
+    menu.append_submenu("File", &file_menu);
+
+    let edit_menu = Menu::new();
+    edit_menu.append("Use First Row as Header", "win.promote_header");
+    edit_menu.append("Use Header as First Row", "win.demote_header");
+    menu.append_submenu("Edit", &edit_menu);
+
+    menu
+}
+
+pub fn show_about_dialog() {
+    let dialog = AboutDialog::new();
+
+    dialog.set_authors(&["Stephen Coakley <scoakley@widen.com>"]);
+    dialog.set_copyright("Copyright (c) 2017 Widen Enterprises, Inc");
+    dialog.set_license(::LICENSE);
+    dialog.set_program_name("Astinus");
+    dialog.set_version(::VERSION);
+
+    dialog.run();
+    dialog.destroy();
+}
+
+
+#[derive(Clone)]
+pub struct MainWindow {
+    builder: Builder,
+    application: Application,
+    window: ApplicationWindow,
+    page_entry: SpinButton,
+    spreadsheet_view: TreeView,
+    status_bar: Statusbar,
+    load_box: ::gtk::Box,
+    load_progress_bar: ProgressBar,
+    delete_dialog: Dialog,
+    open_dialog: FileChooserDialog,
+    open_flatten_check: CheckButton,
+    save_dialog: FileChooserDialog,
+    save_encoding_combo: ComboBoxText,
+    save_bom_check: CheckButton,
+    export_dialog: ExportDialog,
+    import_dialog: ImportDialog,
+    sheet_dialog: SheetDialog,
+    spreadsheet: Rc<RefCell<Option<Spreadsheet>>>,
+    /// Progress of the file loading in the background, if any. The spreadsheet is a partial view until it finishes.
+    loading: Rc<RefCell<Option<Arc<LoadProgress>>>>,
+    page: Rc<Cell<i64>>,
+}
+
+impl MainWindow {
+    pub fn new(application: &Application) -> Self {
+        let builder = Builder::new();
+        builder.add_from_string(include_str!("../main.glade")).unwrap();
+
+        let main = Self {
+            builder: builder.clone(),
+            application: application.clone(),
+            window: builder.get_object("window").unwrap(),
+            page_entry: builder.get_object("page_entry").unwrap(),
+            spreadsheet_view: builder.get_object("spreadsheet_view").unwrap(),
+            status_bar: builder.get_object("status_bar").unwrap(),
+            load_box: builder.get_object("load_box").unwrap(),
+            load_progress_bar: builder.get_object("load_progress_bar").unwrap(),
+            delete_dialog: builder.get_object("delete_dialog").unwrap(),
+            open_dialog: builder.get_object("open_dialog").unwrap(),
+            open_flatten_check: builder.get_object("open_flatten_check").unwrap(),
+            save_dialog: builder.get_object("save_dialog").unwrap(),
+            save_encoding_combo: builder.get_object("save_encoding_combo").unwrap(),
+            save_bom_check: builder.get_object("save_bom_check").unwrap(),
+            export_dialog: ExportDialog::new(&builder),
+            import_dialog: ImportDialog::new(&builder),
+            sheet_dialog: SheetDialog::new(&builder),
+            spreadsheet: Rc::new(RefCell::new(None)),
+            loading: Rc::new(RefCell::new(None)),
+            page: Rc::new(Cell::new(1)),
+        };
+
+        populate_encodings(&main.save_encoding_combo);
+
+        let window: ApplicationWindow = builder.get_object("window").unwrap();
+        window.set_application(Some(application));
+
+        window.add_action(&create_action("open", &main, true, |main| {
+            main.show_open_dialog();
+        }));
+
+        window.add_action(&create_action("save", &main, false, |main| {
+            main.show_save_dialog();
+        }));
+
+        window.add_action(&create_action("close", &main, false, |main| {
+            main.close_file();
+        }));
+
+        window.add_action(&create_action("previous_page", &main, false, |main| {
+            main.go_to_previous_page();
+        }));
+
+        window.add_action(&create_action("next_page", &main, false, |main| {
+            main.go_to_next_page();
+        }));
+
+        window.add_action(&create_action("delete", &main, false, |main| {
+            main.show_delete_dialog();
+        }));
+
+        window.add_action(&create_action("promote_header", &main, false, |main| {
+            main.promote_header();
+        }));
+
+        window.add_action(&create_action("demote_header", &main, false, |main| {
+            main.demote_header();
+        }));
+
+        {
+            let cloned = main.clone();
+            window.connect_delete_event(move |_, _| {
+                cloned.close_file();
+                Inhibit(false)
+            });
+        }
+
+        {
+            let cloned = main.clone();
+            main.page_entry.connect_value_changed(move |page_entry| {
+                cloned.go_to_page(page_entry.get_value_as_int() as i64);
+            });
+        }
+
+        {
+            let cloned = main.clone();
+            let cancel_button: Button = builder.get_object("load_cancel_button").unwrap();
+            cancel_button.connect_clicked(move |_| cloned.close_file());
+        }
+
+        main.update_state();
+
+        main
+    }
+
+    /// Get the main window object.
+    pub fn window(&self) -> ApplicationWindow {
+        self.window.clone()
+    }
+
+    /// Check if a file is currently opened in this window.
+    pub fn is_file_opened(&self) -> bool {
+        self.spreadsheet.borrow().is_some()
+    }
+
+    /// Open a file, loading it in the background.
+    ///
+    /// If the file is a workbook with more than one worksheet, the user chooses which worksheets to open. The first
+    /// one chosen is opened in this window, and the rest in new windows.
+    pub fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        let sheets = Spreadsheet::sheets(&path)?;
+
+        if sheets.len() <= 1 {
+            let load_path = path.clone();
+            self.close_file();
+            self.load_file(&path, move |progress| Spreadsheet::open(&load_path, Some(progress)));
+
+            return Ok(());
+        }
+
+        for (index, sheet) in self.sheet_dialog.run(&sheets).into_iter().enumerate() {
+            let main = if index == 0 {
+                self.close_file();
+                self.clone()
+            } else {
+                let main = MainWindow::new(&self.application);
+                main.window().show_all();
+                main
+            };
+
+            let load_path = path.clone();
+            main.load_file(&path, move |progress| Spreadsheet::open_sheet(&load_path, &sheet, Some(progress)));
+        }
+
+        Ok(())
+    }
+
+    /// Open a file, first letting the user review how it will be read if it is delimited text.
+    ///
+    /// JSON records are read with the options chosen in the open dialog.
+    pub fn import_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        let extension = formats::uncompressed_extension(path);
+
+        if let Some("json") | Some("jsonl") | Some("ndjson") = extension.as_ref().map(String::as_str) {
+            let options = JsonOptions {
+                flatten: self.open_flatten_check.get_active(),
+                ..JsonOptions::default()
+            };
+
+            let load_path = path.to_path_buf();
+            self.close_file();
+            self.load_file(path, move |progress| Spreadsheet::open_json(&load_path, options, Some(progress)));
+
+            return Ok(());
+        }
+
+        // Text files with other extensions are read as the user chooses, starting with the default dialect.
+        let dialect = match Dialect::for_path(path) {
+            Some(dialect) => dialect,
+            None if Spreadsheet::is_text(path) => Dialect::default(),
+            None => return self.open_file(path),
+        };
+        let dialect = formats::sniff_file(path, dialect)?;
+
+        // Files with these extensions are usually laid out in fixed-width columns.
+        let fixed_width = match extension.as_ref().map(String::as_str) {
+            Some("dat") | Some("fwf") | Some("prn") => true,
+            _ => false,
+        };
+
+        if let Some(format) = self.import_dialog.run(path, dialect, fixed_width) {
+            self.close_file();
+
+            let load_path = path.to_path_buf();
+            match format {
+                ImportFormat::Delimited(dialect) => {
+                    self.load_file(path, move |progress| {
+                        Spreadsheet::open_delimited(&load_path, dialect, Some(progress))
+                    });
+                }
+                ImportFormat::FixedWidth(layout) => {
+                    self.load_file(path, move |progress| {
+                        Spreadsheet::open_fixed_width(&load_path, layout, Some(progress))
+                    });
+                }
+                ImportFormat::Indexed(dialect) => {
+                    self.show_spreadsheet(Spreadsheet::open_indexed(path, dialect)?)?;
+                    self.watch_indexing();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check if the active file is viewed in place, without importing it.
+    pub fn is_indexed(&self) -> bool {
+        self.spreadsheet.borrow().as_ref().map_or(false, Spreadsheet::is_indexed)
+    }
+
+    /// Check if a file is loading in the background.
+    pub fn is_loading(&self) -> bool {
+        self.loading.borrow().is_some()
+    }
+
+    /// Load a file on a background thread, showing its progress and the rows loaded so far until it finishes. The load
+    /// is given the progress to report to.
+    fn load_file<F>(&self, path: &Path, load: F)
+        where F: FnOnce(&Arc<LoadProgress>) -> Result<Spreadsheet> + Send + 'static
+    {
+        let progress = Arc::new(LoadProgress::for_file(path));
+        let (sender, receiver) = mpsc::channel();
+
+        {
+            let progress = progress.clone();
+            thread::spawn(move || {
+                // Errors cannot be sent between threads, so only their messages are sent.
+                let result = load(&progress).map_err(|e| e.to_string());
+
+                // The window may have stopped waiting for the load if it was cancelled.
+                let _ = sender.send(result);
+            });
+        }
+
+        *self.loading.borrow_mut() = Some(progress.clone());
+        self.load_progress_bar.set_fraction(0.0);
+        self.load_progress_bar.set_text("Loading...");
+        self.load_box.set_visible(true);
+        self.update_state();
+
+        let cloned = self.clone();
+        timeout_add(LOAD_POLL_INTERVAL, move || {
+            // Stop waiting if the load was cancelled or replaced by another one.
+            if !cloned.loading.borrow().as_ref().map_or(false, |loading| Arc::ptr_eq(loading, &progress)) {
+                return Continue(false);
+            }
+
+            match receiver.try_recv() {
+                Err(TryRecvError::Empty) => {
+                    cloned.update_loading(&progress)
+                        .unwrap_or_else(|e| cloned.show_error_dialog(e));
+                    Continue(true)
+                }
+                Ok(result) => {
+                    cloned.finish_loading(result);
+                    Continue(false)
+                }
+                Err(TryRecvError::Disconnected) => {
+                    cloned.finish_loading(Err(String::from("Loading stopped unexpectedly.")));
+                    Continue(false)
+                }
+            }
+        });
+    }
+
+    /// Show the progress of indexing the file the active spreadsheet is viewed in place from, until it is indexed.
+    ///
+    /// The spreadsheet can be browsed and edited meanwhile, so indexing is shown in the load bar without the window
+    /// counting as loading. Closing the file stops indexing.
+    fn watch_indexing(&self) {
+        let progress = match self.spreadsheet.borrow().as_ref().and_then(Spreadsheet::indexing) {
+            Some(progress) => progress.clone(),
+            None => return,
+        };
+
+        self.load_progress_bar.set_fraction(0.0);
+        self.load_progress_bar.set_text("Indexing...");
+        self.load_box.set_visible(true);
+
+        let cloned = self.clone();
+        timeout_add(LOAD_POLL_INTERVAL, move || {
+            // Stop watching if the file was closed or replaced by another one.
+            let indexing = cloned.spreadsheet.borrow().as_ref()
+                .and_then(Spreadsheet::indexing)
+                .map_or(false, |indexing| Arc::ptr_eq(indexing, &progress));
+
+            let text = format!("Indexing... {} rows", progress.rows());
+            if let Some(fraction) = progress.fraction() {
+                cloned.load_progress_bar.set_fraction(fraction);
+                cloned.load_progress_bar.set_text(format!("{} ({:.0}%)", text, fraction * 100.0).as_str());
+            }
+
+            // Rows appear on the last page as they are indexed.
+            if cloned.get_last_row_offset() - cloned.get_first_row_offset() + 1 < PAGE_SIZE || !indexing {
+                cloned.update_spreadsheet_view()
+                    .unwrap_or_else(|e| cloned.show_error_dialog(e));
+            }
+            cloned.update_state();
+
+            // Another file may be loading or indexing by now, in which case its progress is still shown.
+            let busy = cloned.is_loading() || cloned.spreadsheet.borrow().as_ref()
+                .map_or(false, |spreadsheet| spreadsheet.indexing().is_some());
+            if !busy {
+                cloned.load_box.set_visible(false);
+            }
+
+            Continue(indexing)
+        });
+    }
+
+    /// Show the progress of the file loading in the background, and the rows loaded so far.
+    fn update_loading(&self, progress: &Arc<LoadProgress>) -> Result<()> {
+        let text = format!("Loading... {} rows", progress.rows());
+        match progress.fraction() {
+            Some(fraction) => {
+                self.load_progress_bar.set_fraction(fraction);
+                self.load_progress_bar.set_text(format!("{} ({:.0}%)", text, fraction * 100.0).as_str());
+            }
+            None => {
+                self.load_progress_bar.pulse();
+                self.load_progress_bar.set_text(text.as_str());
+            }
+        }
+
+        // Start browsing once the columns have been loaded.
+        if self.spreadsheet.borrow().is_none() {
+            let view = match Spreadsheet::open_partial(progress)? {
+                Some(ref view) if view.get_column_count() == 0 => return Ok(()),
+                Some(view) => view,
+                None => return Ok(()),
+            };
+
+            *self.spreadsheet.borrow_mut() = Some(view);
+            self.page.set(1);
+            self.refresh_columns();
+
+            return Ok(());
+        }
+
+        // Columns may be added by ragged rows, and rows appear on the last page as they are loaded.
+        let columns_changed = self.spreadsheet_view.get_columns().len() as i64 != self.get_column_count() + 1;
+        if columns_changed {
+            self.prepare_spreadsheet_view();
+        }
+        if columns_changed || self.get_last_row_offset() - self.get_first_row_offset() + 1 < PAGE_SIZE {
+            self.update_spreadsheet_view()?;
+        }
+        self.update_state();
+
+        Ok(())
+    }
+
+    /// Show a spreadsheet that has finished loading in the background, or why it could not be loaded.
+    fn finish_loading(&self, result: ::std::result::Result<Spreadsheet, String>) {
+        let page = self.page.get();
+
+        // Close the partial view before showing the finished spreadsheet.
+        self.loading.borrow_mut().take();
+        self.spreadsheet.borrow_mut().take();
+        self.load_box.set_visible(false);
+
+        match result {
+            Ok(spreadsheet) => {
+                self.show_spreadsheet(spreadsheet)
+                    .unwrap_or_else(|e| self.show_error_dialog(e));
+                self.go_to_page(page);
+            }
+            Err(message) => {
+                self.prepare_spreadsheet_view();
+                self.update_state();
+                self.show_error_dialog(message.into());
+            }
+        }
+    }
+
+    /// Save the active file if one is open, using the options chosen for its format.
+    pub fn save_file<P: AsRef<Path>>(&self, path: P, format: ExportFormat) -> Result<()> {
+        if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+            match format {
+                ExportFormat::Default => spreadsheet.save(path)?,
+                ExportFormat::Json(options) => spreadsheet.save_json(path, options)?,
+                ExportFormat::Parquet(options) => spreadsheet.save_parquet(path, options)?,
+                ExportFormat::Sql(table, options) => spreadsheet.save_sql(path, &table, options)?,
+                ExportFormat::SqliteTable(table) => spreadsheet.save_sqlite_table(path, &table)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Close the active file if one is open, cancelling it if it is still loading.
+    pub fn close_file(&self) {
+        if let Some(progress) = self.loading.borrow_mut().take() {
+            progress.cancel();
+            self.load_box.set_visible(false);
+        }
+
+        if let Some(spreadsheet) = self.spreadsheet.borrow_mut().take() {
+            if spreadsheet.is_dirty() {
+                let dialog = MessageDialog::new(
+                    Some(&self.window),
+                    DIALOG_MODAL,
+                    MessageType::Warning,
+                    ButtonsType::YesNo,
+                    "The current spreadsheet has not been saved. Would you like to save it?"
+                );
+
+                dialog.set_modal(true);
+                dialog.set_position(WindowPosition::CenterOnParent);
+                dialog.set_resizable(false);
+                dialog.set_urgency_hint(true);
+                let response = dialog.run();
+                dialog.destroy();
+
+                let yes: i32 = ResponseType::Yes.into();
+                if response == yes {
+                    self.show_save_dialog();
+                }
+            }
+        }
+
+        self.prepare_spreadsheet_view();
+        self.update_state();
+    }
+
+    /// Get the current page being viewed.
+    pub fn get_current_page(&self) -> i64 {
+        self.page.get()
+    }
+
+    /// Get the total number of pages.
+    pub fn get_page_count(&self) -> i64 {
+        if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+            spreadsheet.get_row_count() / PAGE_SIZE + 1
+        } else {
+            0
+        }
+    }
+
+    /// Get the total number of columns.
+    pub fn get_column_count(&self) -> i64 {
+        if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+            spreadsheet.get_column_count()
+        } else {
+            0
+        }
+    }
+
+    /// Get the total number of rows.
+    pub fn get_row_count(&self) -> i64 {
+        if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+            spreadsheet.get_row_count()
+        } else {
+            0
+        }
+    }
+
+    /// Get the first row number currently being displayed.
+    pub fn get_first_row_offset(&self) -> i64 {
+        (self.get_current_page() - 1) * PAGE_SIZE
+    }
+
+    /// Get the last row number currently being displayed.
+    pub fn get_last_row_offset(&self) -> i64 {
+        min(
+            self.get_first_row_offset() + PAGE_SIZE - 1,
+            self.get_row_count() - 1,
+        )
+    }
+
+    /// Jump the spreadsheet view to a page.
+    pub fn go_to_page(&self, page: i64) {
+        let page = max(1, min(page, self.get_page_count()));
+
+        if self.get_current_page() != page {
+            self.page.set(page);
+
+            self.update_spreadsheet_view()
+                .unwrap_or_else(|e| self.show_error_dialog(e));
+            self.update_state();
+        }
+    }
+
+    /// Go to the next page of spreadsheet rows.
+    pub fn go_to_next_page(&self) {
+        self.go_to_page(self.get_current_page() + 1);
+    }
+
+    /// Go to the previous page of spreadsheet rows.
+    pub fn go_to_previous_page(&self) {
+        self.go_to_page(self.get_current_page() - 1);
+    }
+
+    pub fn show_delete_dialog(&self) {
+        let ok: i32 = ResponseType::Ok.into();
+        if self.delete_dialog.run() == ok {
+            let from_entry: Entry = self.builder.get_object("delete_rows_from_entry").unwrap();
+            let to_entry: Entry = self.builder.get_object("delete_rows_to_entry").unwrap();
+
+            let from: String = from_entry.get_text().unwrap();
+            let to: String = to_entry.get_text().unwrap();
+            info!("from: {} to: {}", from, to);
+            let from: i64 = from.parse().unwrap();
+            let to: i64 = to.parse().unwrap();
+
+            if from <= to {
+                if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+                    spreadsheet.delete_rows(from - 1, to - 1)
+                        .unwrap_or_else(|e| self.show_error_dialog(e));
+                }
+
+                self.update_spreadsheet_view()
+                    .unwrap_or_else(|e| self.show_error_dialog(e));
+                self.update_state();
+            } else {
+                warn!("starting row to delete is larger then ending row: {} > {}", from, to);
+            }
+        }
+
+        self.delete_dialog.hide();
+    }
+
+    /// Use the first row of the active spreadsheet as its column names.
+    pub fn promote_header(&self) {
+        if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+            spreadsheet.promote_row_to_header(0, spreadsheet.dialect().column_naming)
+                .unwrap_or_else(|e| self.show_error_dialog(e));
+        }
+
+        self.refresh_columns();
+    }
+
+    /// Move the column names of the active spreadsheet into its first row.
+    pub fn demote_header(&self) {
+        if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+            spreadsheet.demote_header_to_row(spreadsheet.dialect().column_naming)
+                .unwrap_or_else(|e| self.show_error_dialog(e));
+        }
+
+        self.refresh_columns();
+    }
+
+    pub fn show_open_dialog(&self) {
+        let mut filename = None;
+
+        if self.open_dialog.get_filter().is_none() {
+            let text_filter = FileFilter::new();
+            text_filter.set_name("Text files");
+            text_filter.add_pattern("*.csv");
+            text_filter.add_pattern("*.tsv");
+            text_filter.add_pattern("*.tab");
+            text_filter.add_pattern("*.psv");
+            text_filter.add_pattern("*.ssv");
+            text_filter.add_pattern("*.txt");
+            text_filter.add_pattern("*.dat");
+            text_filter.add_pattern("*.fwf");
+            text_filter.add_pattern("*.prn");
+            self.open_dialog.add_filter(&text_filter);
+
+            let compressed_filter = FileFilter::new();
+            compressed_filter.set_name("Compressed text files");
+            compressed_filter.add_pattern("*.gz");
+            compressed_filter.add_pattern("*.gzip");
+            compressed_filter.add_pattern("*.zst");
+            compressed_filter.add_pattern("*.zstd");
+            compressed_filter.add_pattern("*.bz2");
+            compressed_filter.add_pattern("*.xz");
+            self.open_dialog.add_filter(&compressed_filter);
+
+            let excel_filter = FileFilter::new();
+            excel_filter.set_name("Excel spreadsheet");
+            excel_filter.add_pattern("*.xls");
+            excel_filter.add_pattern("*.xlsx");
+            excel_filter.add_pattern("*.xlsm");
+            self.open_dialog.add_filter(&excel_filter);
+
+            let ods_filter = FileFilter::new();
+            ods_filter.set_name("OpenDocument spreadsheet");
+            ods_filter.add_pattern("*.ods");
+            self.open_dialog.add_filter(&ods_filter);
+
+            let json_filter = FileFilter::new();
+            json_filter.set_name("JSON records");
+            json_filter.add_pattern("*.json");
+            json_filter.add_pattern("*.jsonl");
+            json_filter.add_pattern("*.ndjson");
+            self.open_dialog.add_filter(&json_filter);
+
+            let arrow_filter = FileFilter::new();
+            arrow_filter.set_name("Arrow files");
+            arrow_filter.add_pattern("*.arrow");
+            arrow_filter.add_pattern("*.feather");
+            arrow_filter.add_pattern("*.ipc");
+            self.open_dialog.add_filter(&arrow_filter);
+
+            let sqlite_filter = FileFilter::new();
+            sqlite_filter.set_name("SQLite databases");
+            sqlite_filter.add_pattern("*.db");
+            sqlite_filter.add_pattern("*.sqlite");
+            sqlite_filter.add_pattern("*.sqlite3");
+            self.open_dialog.add_filter(&sqlite_filter);
+
+            let parquet_filter = FileFilter::new();
+            parquet_filter.set_name("Parquet files");
+            parquet_filter.add_pattern("*.parquet");
+            self.open_dialog.add_filter(&parquet_filter);
+
+            let zip_filter = FileFilter::new();
+            zip_filter.set_name("ZIP archives");
+            zip_filter.add_pattern("*.zip");
+            self.open_dialog.add_filter(&zip_filter);
+        }
+
+        let ok: i32 = ResponseType::Ok.into();
+        if self.open_dialog.run() == ok {
+            filename = self.open_dialog.get_filename();
+        }
+        self.open_dialog.hide();
+
+        if let Some(filename) = filename {
+            self.import_file(filename)
+                .unwrap_or_else(|e| self.show_error_dialog(e));
+        }
+    }
+
+    pub fn show_save_dialog(&self) {
+        let mut filename = None;
+
+        // Default to saving in the encoding the file was opened with.
+        if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+            self.save_encoding_combo.set_active_id(spreadsheet.encoding().name());
+            self.save_bom_check.set_active(spreadsheet.dialect().bom);
+        }
+
+        let ok: i32 = ResponseType::Ok.into();
+        if self.save_dialog.run() == ok {
+            filename = self.save_dialog.get_filename();
+
+            let encoding = self.save_encoding_combo.get_active_id()
+                .and_then(|id| Encoding::for_label(id.as_bytes()));
+
+            if let (Some(spreadsheet), Some(encoding)) = (self.spreadsheet.borrow().as_ref(), encoding) {
+                spreadsheet.set_encoding(encoding, self.save_bom_check.get_active());
+            }
+        }
+        self.save_dialog.hide();
+
+        if let Some(filename) = filename {
+            let table = self.spreadsheet.borrow().as_ref().map(|spreadsheet| spreadsheet.table_name(&filename));
+
+            if let Some(format) = self.export_dialog.run(&filename, &table.unwrap_or_default()) {
+                self.save_file(filename, format)
+                    .unwrap_or_else(|e| self.show_error_dialog(e));
+            }
+        }
+    }
+
+    fn show_error_dialog(&self, error: Box<Error>) {
+        error!("Error: {:?}", error);
+        let message = format!("Error: {:?}", error);
+
+        let window = self.window();
+        let dialog = MessageDialog::new(
+            Some(&window),
+            DIALOG_MODAL,
+            MessageType::Error,
+            ButtonsType::Ok,
+            &message
+        );
+
+        dialog.set_modal(true);
+        dialog.set_position(WindowPosition::CenterOnParent);
+        dialog.set_urgency_hint(true);
+        dialog.run();
+        dialog.destroy();
+    }
+
+    /// Update the UI based on the current state of the window.
+    fn update_state(&self) {
+        // Update window actions. A file that is still loading can be browsed, but not changed.
+        let file_actions = self.is_file_opened();
+        let edit_actions = file_actions && !self.is_loading();
+        let row_actions = edit_actions && !self.is_indexed();
+        self.set_action_enabled("save", edit_actions);
+        self.set_action_enabled("close", file_actions || self.is_loading());
+        self.set_action_enabled("previous_page", file_actions && self.get_current_page() > 1);
+        self.set_action_enabled("next_page", file_actions && self.get_current_page() < self.get_page_count());
+        self.set_action_enabled("delete", row_actions);
+        self.set_action_enabled("promote_header", row_actions && self.get_row_count() > 0);
+        self.set_action_enabled("demote_header", row_actions);
+
+        // Update the page entry.
+        self.page_entry.set_range(1.0, self.get_page_count() as f64);
+        self.page_entry.set_value(self.get_current_page() as f64);
+
+        // Update the status bar contents.
+        let mut page_status = format!(
+            "Page {} of {} (rows {} - {}) of {} rows",
+            self.get_current_page(),
+            self.get_page_count(),
+            self.get_first_row_offset() + 1,
+            self.get_last_row_offset() + 1,
+            self.get_row_count(),
+        );
+
+        // Show the dialect a text file was read with, since it may have been guessed.
+        if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+            if spreadsheet.path().and_then(Dialect::for_path).is_some() {
+                page_status = format!("{} | {}", page_status, spreadsheet.dialect());
+            }
+        }
+        self.status_bar.remove_all(0);
+        self.status_bar.push(0, &page_status);
+
+        // Update the titlebar.
+        if let Some(spreadsheet) = self.spreadsheet.borrow().as_ref() {
+            let title = format!("Astinus - {}", spreadsheet.name());
+            self.window.set_title(&title);
+        } else {
+            self.window.set_title("Astinus");
+        }
+    }
+
+    /// Display a newly opened spreadsheet in the window.
+    fn show_spreadsheet(&self, spreadsheet: Spreadsheet) -> Result<()> {
+        *self.spreadsheet.borrow_mut() = Some(spreadsheet);
+
+        self.page.set(1);
+        self.prepare_spreadsheet_view();
+        self.update_spreadsheet_view()?;
+        self.update_state();
+        self.show_load_report();
+
+        Ok(())
+    }
+
+    /// Tell the user about any problems found in the file while loading the current spreadsheet, such as ragged or
+    /// malformed records.
+    fn show_load_report(&self) {
+        let report = match self.spreadsheet.borrow().as_ref() {
+            Some(spreadsheet) => spreadsheet.load_report(),
+            None => return,
+        };
+
+        if report.is_empty() {
+            return;
+        }
+
+        let mut message = format!("{} problems were found while loading the file:\n", report.len());
+        for diagnostic in report.diagnostics.iter().take(REPORT_PROBLEMS) {
+            message = format!("{}\n{}", message, diagnostic);
+        }
+        if report.len() > REPORT_PROBLEMS {
+            message = format!("{}\nAnd {} more problems.", message, report.len() - REPORT_PROBLEMS);
+
+            // Summarize where the problems that are not listed are, by kind.
+            message = format!("{}\n", message);
+            for &kind in &[DiagnosticKind::Malformed, DiagnosticKind::Ragged, DiagnosticKind::Unparseable] {
+                let lines = report.lines(kind);
+                if lines.is_empty() {
+                    continue;
+                }
+
+                let listed = lines.iter().take(REPORT_LINES).map(u64::to_string).collect::<Vec<_>>().join(", ");
+                message = format!("{}\nLines with {} records: {}", message, kind, listed);
+                if lines.len() > REPORT_LINES {
+                    message = format!("{} and {} more", message, lines.len() - REPORT_LINES);
+                }
+            }
+        }
+
+        let window = self.window();
+        let dialog = MessageDialog::new(
+            Some(&window),
+            DIALOG_MODAL,
+            MessageType::Warning,
+            ButtonsType::Ok,
+            &message
+        );
+
+        dialog.set_modal(true);
+        dialog.set_position(WindowPosition::CenterOnParent);
+        dialog.run();
+        dialog.destroy();
+    }
+
+    /// Rebuild the spreadsheet view after the columns of the current spreadsheet have changed.
+    fn refresh_columns(&self) {
+        self.prepare_spreadsheet_view();
+        self.update_spreadsheet_view()
+            .unwrap_or_else(|e| self.show_error_dialog(e));
+        self.update_state();
+    }
+
+    /// Enable or disable a window action.
+    fn set_action_enabled(&self, action: &str, enabled: bool) {
+        let action: SimpleAction = self.window().lookup_action(action).unwrap().downcast().unwrap();
+        action.set_enabled(enabled);
+    }
+
+    /// Prepare the spreadsheet view for displaying the current spreadsheet.
+    fn prepare_spreadsheet_view(&self) {
+        let spreadsheet = self.spreadsheet.borrow();
+
+        // Remove the previous model.
+        self.spreadsheet_view.set_model::<TreeModel>(None);
+
+        // Remove all existing columns.
+        for column in self.spreadsheet_view.get_columns() {
+            self.spreadsheet_view.remove_column(&column);
+        }
+
+        if let Some(spreadsheet) = spreadsheet.as_ref() {
+            // Row ID column.
+            {
+                let column = TreeViewColumn::new();
+                column.set_resizable(true);
+                column.set_title("#");
+
+                let renderer = CellRendererText::new();
+                column.pack_start(&renderer, true);
+                column.add_attribute(&renderer, "text", 0);
+
+                self.spreadsheet_view.append_column(&column);
+            }
+
+            // Populate new columns.
+            for (index, title) in spreadsheet.get_columns().into_iter().enumerate() {
+                let column = TreeViewColumn::new();
+                column.set_resizable(true);
+                column.set_title(&title);
+
+                let renderer = CellRendererText::new();
+                renderer.set_property_editable(!self.is_loading());
+                let cloned = self.clone();
+                renderer.connect_edited(move |r, p, v| {
+                    cloned.on_edit(r, index as i64, p, v)
+                });
+
+                column.pack_start(&renderer, true);
+                column.add_attribute(&renderer, "text", index as i32 + 1);
+
+                self.spreadsheet_view.append_column(&column);
+            }
+
+            // Create a new model.
+            let mut column_types = Vec::new();
+            column_types.push(Type::String);
+            for _ in 0..spreadsheet.get_column_count() {
+                column_types.push(Type::String);
+            }
+
+            let model = ListStore::new(&column_types);
+            self.spreadsheet_view.set_model(Some(&model));
+        }
+    }
+
+    /// Update the current spreadsheet view.
+    fn update_spreadsheet_view(&self) -> Result<()> {
+        // Compute start and end ranges.
+        let start = self.get_first_row_offset();
+        let end = self.get_last_row_offset();
+
+        let spreadsheet = self.spreadsheet.borrow();
+
+        if let Some(spreadsheet) = spreadsheet.as_ref() {
+            if let Some(model) = self.spreadsheet_view.get_model() {
+                let model: ListStore = model.downcast().unwrap();
+
+                model.clear();
+
+                for (row_num, row) in spreadsheet.get_rows(start, end)?.into_iter().enumerate() {
+                    let iter = model.append();
+                    let row_num = (row_num as i64 + start + 1).to_value();
+                    model.set_value(&iter, 0, &row_num);
+
+                    for (column, cell) in row.into_iter().enumerate() {
+                        let value = cell.as_ref().into();
+                        model.set_value(&iter, column as u32 + 1, &value);
+                    }
+                }
+
+                self.spreadsheet_view.set_model(Some(&model));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_edit(&self, _: &CellRendererText, column: i64, path: TreePath, value: &str) {
+        let spreadsheet = self.spreadsheet.borrow();
+        let row_offset = path.get_indices()[0] as i64;
+        let row = self.get_first_row_offset() + row_offset;
+
+        if let Some(spreadsheet) = spreadsheet.as_ref() {
+            spreadsheet.set_cell(row as i64, column, Some(value.to_string()))
+                .unwrap_or_else(|e| self.show_error_dialog(e));
+        }
+
+        if let Some(model) = self.spreadsheet_view.get_model() {
+            let model: ListStore = model.downcast().unwrap();
+            let iter = model.get_iter(&path).unwrap();
+
+            let value = value.to_value();
+            model.set_value(&iter, column as u32 + 1, &value);
+        }
+    }
+}
+
+/// Fill a combo box with the available text encodings, using the encoding names as IDs.
+fn populate_encodings(combo: &ComboBoxText) {
+    for encoding in formats::encodings() {
+        combo.append(encoding.name(), encoding.name());
+    }
+}
+
+/// Create an action mapping.
+pub fn create_action<T, F>(name: &str, context: &T, enabled: bool, f: F) -> SimpleAction
+    where T: Clone + 'static, F: Fn(T) + 'static
+{
+    let action = SimpleAction::new(name, None);
+    action.set_enabled(enabled);
+
+    let context = context.clone();
+    action.connect_activate(move |_, _| {
+        let cloned = context.clone();
+        f(cloned);
+    });
+
+    action
+}
@@ -0,0 +1,63 @@
+use gtk::*;
+
+
+/// Dialog for choosing which worksheets of a workbook to open.
+#[derive(Clone)]
+pub struct SheetDialog {
+    dialog: Dialog,
+    sheet_view: TreeView,
+}
+
+impl SheetDialog {
+    pub fn new(builder: &Builder) -> Self {
+        let sheets = Self {
+            dialog: builder.get_object("sheet_dialog").unwrap(),
+            sheet_view: builder.get_object("sheet_view").unwrap(),
+        };
+
+        let column = TreeViewColumn::new();
+        let renderer = CellRendererText::new();
+        column.pack_start(&renderer, true);
+        column.add_attribute(&renderer, "text", 0);
+        sheets.sheet_view.append_column(&column);
+
+        // Double-clicking a worksheet opens it right away.
+        {
+            let dialog = sheets.dialog.clone();
+            sheets.sheet_view.connect_row_activated(move |_, _, _| {
+                dialog.response(ResponseType::Ok.into());
+            });
+        }
+
+        sheets
+    }
+
+    /// Show the dialog for the given worksheet names, with the first worksheet selected.
+    ///
+    /// Returns the names of the worksheets chosen by the user in workbook order, or nothing if cancelled.
+    pub fn run(&self, sheets: &[String]) -> Vec<String> {
+        let model = ListStore::new(&[Type::String]);
+        for sheet in sheets {
+            let iter = model.append();
+            model.set_value(&iter, 0, &sheet.to_value());
+        }
+        self.sheet_view.set_model(Some(&model));
+
+        let selection = self.sheet_view.get_selection();
+        selection.select_path(&TreePath::new_first());
+
+        let response = self.dialog.run();
+        self.dialog.hide();
+
+        let ok: i32 = ResponseType::Ok.into();
+        if response != ok {
+            return Vec::new();
+        }
+
+        let (paths, _) = selection.get_selected_rows();
+        paths.iter()
+            .filter_map(|path| path.get_indices().first().and_then(|&index| sheets.get(index as usize)))
+            .cloned()
+            .collect()
+    }
+}